@@ -0,0 +1,550 @@
+use crate::matcher::Matcher;
+use crate::output::Format;
+use crate::selector::{parse_matcher, SpaceTarget, WindowTarget};
+use crate::subscription::TOPICS;
+use crate::yabai::{Layout, ResizeHandle, SpaceSelector, WindowSelector};
+use anyhow::{bail, Context, Result};
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::{ArgAction, ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Thin wrapper around yabai that adds convenient and intuitive fixes.
+///
+/// Commands not listed below are passed through to yabai, e.g. `yctrl query --windows`.
+/// Without a command, the daemon is started.
+#[derive(Parser, Debug)]
+#[command(name = "yctrl", version)]
+pub struct Cli {
+    /// Print diagnostics to stderr, repeat for more details.
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Milliseconds given to yabai to respond to each request, defaults to 2000.
+    #[arg(long, value_name = "MS", global = true)]
+    pub yabai_timeout: Option<u64>,
+    #[command(subcommand)]
+    pub command: Option<Cmd>,
+}
+
+impl Cli {
+    /// Parse command line arguments, exiting with usage on error.
+    pub fn parse_args(args: impl IntoIterator<Item = String>) -> Self {
+        Self::try_parse_args(args).unwrap_or_else(|e| e.exit())
+    }
+
+    fn try_parse_args(args: impl IntoIterator<Item = String>) -> Result<Self, clap::Error> {
+        let args = args.into_iter().collect::<Vec<_>>();
+        // Without a command, `-m` would be dropped and the daemon started.
+        if args.len() == 2 && args[1] == "-m" {
+            return Err(Self::command().error(
+                ErrorKind::MissingSubcommand,
+                "-m expects a command: -m <domain> ..., e.g. -m window --focus next",
+            ));
+        }
+        Self::try_parse_from(normalize(args))
+    }
+}
+
+/// Normalize yabai style (`-m window --focus next`) and dashless (`window focus next`)
+/// arguments to the latter, turning window id or space index provided before the command
+/// into an option.
+fn normalize(mut args: Vec<String>) -> Vec<String> {
+    // NOTE: args[0] is the program name.
+    if args.get(1).is_some_and(|a| a == "-m") {
+        args.remove(1);
+    }
+
+    let option = match args.get(1).map(String::as_str) {
+        Some("window") => "--id",
+        Some("space") => "--index",
+        _ => return args,
+    };
+    if args.get(2).is_some_and(|a| a.parse::<u32>().is_ok()) {
+        args.insert(2, option.to_string());
+    }
+
+    let command_pos = if args.get(2).is_some_and(|a| a == option) {
+        4
+    } else {
+        2
+    };
+    if let Some(command) = args.get_mut(command_pos) {
+        if let Some(stripped) = command
+            .strip_prefix("--")
+            .filter(|c| !c.is_empty() && *c != "help" && *c != &option[2..])
+        {
+            *command = stripped.to_string();
+        }
+    }
+
+    args
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Cmd {
+    /// Control windows.
+    Window(WindowArgs),
+    /// Control spaces.
+    Space(SpaceArgs),
+    /// Toggle the scratchpad with the given tag.
+    Scratchpad { tag: String },
+    /// Get or set yctrl and yabai configuration.
+    #[command(arg_required_else_help = true)]
+    Config(ConfigArgs),
+    /// Query windows, spaces or displays.
+    Query(QueryArgs),
+    /// Run the daemon handling yabai events and runtime commands.
+    Daemon(DaemonArgs),
+    /// Re-run requests recorded with `daemon --trace` against the recorded yabai responses.
+    Replay { trace: PathBuf },
+    /// Set the layout of the focused space.
+    Layout { layout: Layout },
+    /// Stream processed events and yctrl state changes as JSON lines.
+    Subscribe {
+        /// Topics to stream, all by default.
+        #[arg(long, value_delimiter = ',', value_parser = PossibleValuesParser::new(TOPICS))]
+        events: Vec<String>,
+    },
+    /// Print shell completions.
+    Completions { shell: clap_complete::Shell },
+    /// Passed through to yabai.
+    #[command(external_subcommand)]
+    Yabai(Vec<String>),
+}
+
+#[derive(Args, Debug, Default)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct DaemonArgs {
+    /// Record requests and yabai exchanges to the given file, see `yctrl replay`.
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+    /// Serve Prometheus metrics on a TCP port, `host:port` or Unix socket path.
+    #[arg(long, value_name = "ADDR")]
+    pub metrics_listen: Option<String>,
+    #[command(flatten)]
+    pub log: LogArgs,
+    #[command(subcommand)]
+    pub command: Option<DaemonCmd>,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct LogArgs {
+    /// Log filter, e.g. `debug` or `info,yctrl::runtime=trace`, defaults to RUST_LOG or info.
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+    /// Directory of the `yctrl.log` file, defaults to /tmp.
+    #[arg(long, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+    /// Start a new log file every day.
+    #[arg(long, value_enum, default_value_t)]
+    pub log_rotation: LogRotation,
+    /// Number of rotated log files to keep, defaults to 7.
+    #[arg(long, value_name = "FILES")]
+    pub log_retention: Option<usize>,
+    /// Format of log lines.
+    #[arg(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+    /// Prefix log lines with a timestamp.
+    #[arg(long)]
+    pub log_timestamps: bool,
+    /// Only log to the log file.
+    #[arg(long)]
+    pub no_log_stdout: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DaemonCmd {
+    /// Change the log filter of the running daemon, e.g. `debug` or `info,yctrl::hook=trace`.
+    LogLevel { filter: String },
+    /// Print handler and yabai request latencies, error and retry counts.
+    Metrics {
+        /// Print in the Prometheus text format.
+        #[arg(long)]
+        prometheus: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct WindowArgs {
+    /// Window id to act on instead of the focused window, also accepted before the command.
+    #[arg(long)]
+    pub id: Option<u32>,
+    #[command(subcommand)]
+    pub command: WindowCmd,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WindowCmd {
+    /// Focus a window: <SELECTOR>, `back` or `mru <next|prev>`.
+    Focus {
+        #[arg(value_name = "SELECTOR")]
+        target: WindowFocus,
+        /// Direction of `mru` cycling.
+        #[arg(required_if_eq("target", "mru"))]
+        direction: Option<Cycle>,
+    },
+    /// Swap with the selected window.
+    Swap { selector: WindowTarget },
+    /// Warp into the selected window.
+    Warp { selector: WindowTarget },
+    /// Move to the selected space, focusing it.
+    Space { selector: SpaceTarget },
+    /// Stack onto the selected window, e.g. the neighbour to the `west`.
+    Stack { selector: WindowSelector },
+    /// Take out of its stack, back into the tiling.
+    Unstack,
+    /// List stacked windows of every space.
+    Stacks,
+    /// Toggle monocle mode of the focused space, zooming every focused window.
+    Monocle,
+    /// Grow (or shrink) towards the given side.
+    Inc { side: Side },
+    /// Toggle between the largest and the smallest window.
+    Make {
+        #[arg(value_parser = ["master"])]
+        role: String,
+    },
+    /// Resize by <handle>:<dx>:<dy>.
+    Resize {
+        #[arg(value_parser = parse_resize)]
+        resize: (ResizeHandle, i32, i32),
+    },
+    /// Focus the matching window, cycling through matches, or run the command if none.
+    Raise {
+        /// Window matcher: `app:<name>`, `title:<title>`, `app:/<regex>/` or `title:/<regex>/`.
+        #[arg(value_parser = parse_matcher)]
+        matcher: Matcher,
+        /// Seconds to wait for the window of the launched command (defaults to 5).
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Command run when no window matches, after `--`.
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Passed through to yabai, e.g. `yctrl window toggle float`.
+    #[command(external_subcommand)]
+    Yabai(Vec<String>),
+}
+
+#[derive(Args, Debug)]
+pub struct SpaceArgs {
+    /// Space index to act on instead of the focused space, also accepted before the command.
+    #[arg(long)]
+    pub index: Option<u32>,
+    #[command(subcommand)]
+    pub command: SpaceCmd,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SpaceCmd {
+    /// Focus a space: <SELECTOR> or `back [N]`.
+    Focus {
+        #[arg(value_name = "SELECTOR")]
+        target: SpaceFocus,
+        /// Number of steps back in history.
+        #[arg(default_value_t = 1)]
+        steps: usize,
+    },
+    /// Move to the selected space.
+    Move { selector: SpaceTarget },
+    /// Swap with the selected space.
+    Swap { selector: SpaceTarget },
+    /// Switch to the space profile with the given name.
+    Profile { name: String },
+    /// Passed through to yabai, e.g. `yctrl space balance`.
+    #[command(external_subcommand)]
+    Yabai(Vec<String>),
+}
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("kind").required(true).args(["windows", "spaces", "displays"])))]
+pub struct QueryArgs {
+    /// Query windows, optionally only the given comma separated properties.
+    #[arg(long, value_name = "PROPERTIES")]
+    pub windows: Option<Option<String>>,
+    /// Query spaces, optionally only the given comma separated properties.
+    #[arg(long, value_name = "PROPERTIES")]
+    pub spaces: Option<Option<String>>,
+    /// Query displays, optionally only the given comma separated properties.
+    #[arg(long, value_name = "PROPERTIES")]
+    pub displays: Option<Option<String>>,
+    /// Only the given window, or windows of the given kind (defaults to focused).
+    #[arg(long, value_name = "WINDOW_SEL")]
+    pub window: Option<Option<String>>,
+    /// Only the given space, or spaces of the given kind (defaults to focused).
+    #[arg(long, value_name = "SPACE_SEL")]
+    pub space: Option<Option<String>>,
+    /// Only the given display, or displays of the given kind (defaults to focused).
+    #[arg(long, value_name = "DISPLAY_SEL")]
+    pub display: Option<Option<String>>,
+    /// Output format: json, table or jq-style fields such as `.id,.frame.w`.
+    #[arg(long, default_value_t = Format::Json)]
+    pub format: Format,
+}
+
+impl QueryArgs {
+    /// Queried kind of object.
+    pub fn kind(&self) -> &'static str {
+        if self.windows.is_some() {
+            "windows"
+        } else if self.spaces.is_some() {
+            "spaces"
+        } else {
+            "displays"
+        }
+    }
+
+    /// Properties queried, when not all of them.
+    pub fn properties(&self) -> Option<Vec<String>> {
+        let properties = [&self.windows, &self.spaces, &self.displays]
+            .into_iter()
+            .find_map(|kind| kind.clone().flatten())?;
+        Some(
+            properties
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .collect(),
+        )
+    }
+
+    /// Arguments as expected by yabai.
+    pub fn yabai_args(&self) -> Vec<String> {
+        let mut args = vec!["query".to_string()];
+        for (option, value) in [
+            ("--windows", &self.windows),
+            ("--spaces", &self.spaces),
+            ("--displays", &self.displays),
+            ("--window", &self.window),
+            ("--space", &self.space),
+            ("--display", &self.display),
+        ] {
+            if let Some(value) = value {
+                args.push(option.to_string());
+                args.extend(value.clone());
+            }
+        }
+        args
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    /// Print the JSON schema of the configuration file.
+    #[arg(long, exclusive = true)]
+    pub schema: bool,
+    /// List yctrl configuration.
+    #[arg(long, exclusive = true)]
+    pub list: bool,
+    /// Print the value of the given key.
+    #[arg(long, value_name = "KEY", exclusive = true)]
+    pub get: Option<String>,
+    /// Space the yabai key applies to.
+    #[arg(long, value_name = "SPACE")]
+    pub space: Option<SpaceSelector>,
+    /// Key, those prefixed with `yctrl_` are handled by yctrl, the rest by yabai.
+    pub key: Option<String>,
+    /// Value to set, the key is printed if omitted.
+    #[arg(allow_hyphen_values = true, trailing_var_arg = true)]
+    pub value: Vec<String>,
+}
+
+/// Target of window focus.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowFocus {
+    /// Previously focused window.
+    Back,
+    /// Most recently used windows, in the given direction.
+    Mru,
+    Select(WindowTarget),
+}
+
+/// Target of space focus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpaceFocus {
+    /// Space visited the given number of changes ago.
+    Back,
+    Select(SpaceTarget),
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cycle {
+    Next,
+    Prev,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Daily,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for WindowFocus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "back" => Self::Back,
+            "mru" => Self::Mru,
+            s => Self::Select(s.parse()?),
+        })
+    }
+}
+
+impl FromStr for SpaceFocus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "back" => Self::Back,
+            s => Self::Select(s.parse()?),
+        })
+    }
+}
+
+/// Parse `<handle>:<dx>:<dy>` resize argument.
+fn parse_resize(resize: &str) -> Result<(ResizeHandle, i32, i32)> {
+    let parts = resize.split(':').collect::<Vec<_>>();
+    let [handle, dx, dy] = parts[..] else {
+        bail!("Invalid resize {resize}, expected <handle>:<dx>:<dy>");
+    };
+    let parse = |v: &str| {
+        v.parse::<i32>()
+            .with_context(|| format!("Invalid resize value {v}"))
+    };
+    Ok((handle.parse()?, parse(dx)?, parse(dy)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> String {
+        let args = format!("yctrl {args}");
+        let cli = Cli::try_parse_from(normalize(shell_words::split(&args).unwrap())).unwrap();
+        format!("{:?}", cli.command)
+    }
+
+    #[test]
+    fn yabai_syntax() {
+        for (dashless, yabai) in [
+            ("window focus next", "window --focus next"),
+            ("window focus mru prev", "window --focus mru prev"),
+            ("space 2 focus web", "space 2 --focus web"),
+            ("window 12 toggle float", "window 12 --toggle float"),
+            ("window stack west", "window --stack west"),
+        ] {
+            assert_eq!(parse(dashless), parse(yabai), "{yabai}");
+            assert_eq!(parse(dashless), parse(&format!("-m {yabai}")), "-m {yabai}");
+        }
+    }
+
+    #[test]
+    fn yabai_timeout() {
+        let cli =
+            Cli::try_parse_from(["yctrl", "query", "--windows", "--yabai-timeout", "500"]).unwrap();
+        assert_eq!(cli.yabai_timeout, Some(500));
+        // Also applies to the daemon, started without a command.
+        let cli = Cli::try_parse_from(["yctrl", "--yabai-timeout", "5000"]).unwrap();
+        assert_eq!(cli.yabai_timeout, Some(5000));
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn bare_message_flag() {
+        let args = ["yctrl", "-m"].map(String::from);
+        let error = Cli::try_parse_args(args).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingSubcommand);
+        assert!(error.to_string().contains("-m <domain>"));
+    }
+
+    #[test]
+    fn id_option() {
+        assert_eq!(
+            parse("window 12 space next"),
+            parse("window --id 12 --space next")
+        );
+        assert_eq!(
+            parse("space 2 balance"),
+            parse("-m space --index 2 --balance")
+        );
+    }
+
+    #[test]
+    fn daemon() {
+        let cli = Cli::try_parse_from(["yctrl", "daemon", "--log-rotation", "daily"]).unwrap();
+        let Some(Cmd::Daemon(args)) = cli.command else {
+            panic!("expected daemon, got {:?}", cli.command);
+        };
+        assert_eq!(args.log.log_rotation, LogRotation::Daily);
+        assert!(args.command.is_none());
+
+        assert!(parse("daemon log-level info,yctrl::hook=debug").contains("LogLevel"));
+        assert!(
+            Cli::try_parse_from(["yctrl", "daemon", "--trace", "t", "log-level", "info"]).is_err()
+        );
+    }
+
+    #[test]
+    fn pass_through() {
+        assert_eq!(
+            parse("-m rule --add app=Finder"),
+            format!(
+                "{:?}",
+                Some(Cmd::Yabai(vec![
+                    "rule".into(),
+                    "--add".into(),
+                    "app=Finder".into()
+                ]))
+            )
+        );
+    }
+
+    #[test]
+    fn raise() {
+        assert_eq!(
+            parse("window raise app:Slack -- open -a Slack"),
+            format!(
+                "{:?}",
+                Some(Cmd::Window(WindowArgs {
+                    id: None,
+                    command: WindowCmd::Raise {
+                        matcher: Matcher::app_exact("Slack"),
+                        timeout: None,
+                        command: vec!["open".into(), "-a".into(), "Slack".into()],
+                    }
+                }))
+            )
+        );
+    }
+
+    #[test]
+    fn query() {
+        let args =
+            normalize(shell_words::split("yctrl -m query --windows id,app --space 1").unwrap());
+        let Some(Cmd::Query(query)) = Cli::try_parse_from(args).unwrap().command else {
+            panic!("not a query");
+        };
+        assert_eq!(query.kind(), "windows");
+        assert_eq!(query.format, Format::Json);
+        assert_eq!(
+            query.yabai_args(),
+            ["query", "--windows", "id,app", "--space", "1"]
+        );
+        assert_eq!(query.properties(), Some(vec!["id".into(), "app".into()]));
+    }
+}
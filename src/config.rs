@@ -1,44 +1,157 @@
+use crate::hook::Hook;
+use crate::profile::SpaceProfile;
 use crate::scratchpad::Scratchpad;
-use anyhow::Result;
+use crate::yabai::Window;
+use anyhow::{anyhow, bail, Context, Error, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-#[derive(Debug)]
+/// Prefix used to distinguish yctrl config keys from yabai ones.
+pub const KEY_PREFIX: &str = "yctrl_";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
 pub struct Config {
+    /// Destroy a space when switching away from it leaves it empty.
     auto_close_empty_spaces: bool,
-    scratchpad_grid: String,
+    /// Grid used to place scratchpad windows.
+    scratchpad_grid: Grid,
+    /// Scratchpads that can be toggled with `yctrl scratchpad <tag>`.
     scratchpads: Vec<Scratchpad>,
+    /// Labels assigned to spaces by index, usable wherever a space is selected.
+    space_labels: Vec<String>,
+    /// Space profiles by name, a space uses the profile named after its label.
+    space_profiles: BTreeMap<String, SpaceProfile>,
+    /// Maximum number of tiled windows per space, unless set by the space profile.
+    max_windows: Option<usize>,
+    /// Focus windows moved to another space because their space was full.
+    follow_overflow: bool,
+    /// Window to focus when focus is lost, e.g. on space change or window destroy.
+    focus_restore: FocusRestore,
+    /// Move windows focused with `window raise` to the current space.
+    raise_to_current_space: bool,
+    /// Apps (e.g. `Alacritty`) whose windows are replaced by windows launched from them.
+    swallow_terminals: Vec<String>,
+    /// Commands run after events, e.g. `{on: "window_created", run: ["notify.sh"]}`.
+    hooks: Vec<Hook>,
+    /// Maximum number of hooks running at once.
+    hook_concurrency: usize,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             auto_close_empty_spaces: true,
-            scratchpad_grid: "6:4:1:1:2:4".into(),
+            scratchpad_grid: Grid::new(6, 4, 1, 1, 2, 4).unwrap(),
             scratchpads: vec![],
+            space_labels: vec![],
+            space_profiles: BTreeMap::new(),
+            max_windows: None,
+            follow_overflow: false,
+            focus_restore: FocusRestore::Mouse,
+            raise_to_current_space: false,
+            swallow_terminals: vec![],
+            hooks: vec![],
+            hook_concurrency: 4,
         }
     }
 }
 
 impl Config {
-    pub fn set_auto_close_empty_spaces_with_str(&mut self, value: &str) -> Result<()> {
-        self.auto_close_empty_spaces = value.parse()?;
-        Ok(())
+    /// Default location of the configuration file.
+    pub fn path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+        Some(base.join("yctrl").join("config.json5"))
     }
 
-    pub fn set_scratchpad_grid_with_str(&mut self, value: &str) -> Result<()> {
-        self.scratchpad_grid = value.parse()?;
-        Ok(())
+    /// Parse a json5 configuration, rejecting unknown keys.
+    pub fn parse(content: &str) -> Result<Self> {
+        let value: Value = json5::from_str(content)?;
+        let Value::Object(map) = &value else {
+            bail!("Configuration must be an object");
+        };
+        for key in map.keys().filter(|k| k.as_str() != "$schema") {
+            Self::field(key)?;
+        }
+        serde_json::from_value(value).map_err(Error::new)
+    }
+
+    /// JSON Schema describing the configuration file.
+    pub fn schema() -> Result<String> {
+        serde_json::to_string_pretty(&schemars::schema_for!(Config)).map_err(Error::new)
     }
 
-    pub fn set_scratchpads_with_str(&mut self, scratchpads: &str) -> Result<()> {
-        self.scratchpads = json5::from_str(scratchpads)?;
+    /// Update `key` from its raw string value, validating the result.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let field = Self::field(key)?;
+        let value = json5::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+        let mut config = serde_json::to_value(&*self)?;
+        config[field] = value;
+        *self =
+            serde_json::from_value(config).with_context(|| format!("Invalid value for {key}"))?;
         Ok(())
     }
 
+    /// Current value of `key`, strings are returned without quotes.
+    pub fn get(&self, key: &str) -> Result<String> {
+        let field = Self::field(key)?;
+        match &serde_json::to_value(self)?[field] {
+            Value::String(value) => Ok(value.clone()),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    /// All keys along with their current values, one per line.
+    pub fn list(&self) -> Result<String> {
+        Ok(Self::fields()
+            .iter()
+            .map(|field| Ok(format!("{KEY_PREFIX}{field} {}", self.get(field)?)))
+            .collect::<Result<Vec<_>>>()?
+            .join("\n"))
+    }
+
+    /// Names of every configuration field.
+    fn fields() -> Vec<String> {
+        match serde_json::to_value(Self::default()) {
+            Ok(Value::Object(map)) => map.keys().cloned().collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Resolve a key, with or without prefix, to a field name.
+    fn field(key: &str) -> Result<String> {
+        let name = key.strip_prefix(KEY_PREFIX).unwrap_or(key);
+        let fields = Self::fields();
+        if fields.iter().any(|f| f == name) {
+            return Ok(name.to_string());
+        }
+
+        let suggestion = fields
+            .iter()
+            .map(|f| (strsim::levenshtein(name, f), f))
+            .filter(|(distance, _)| *distance <= 4)
+            .min_by_key(|(distance, _)| *distance);
+
+        match suggestion {
+            Some((_, field)) => {
+                bail!("Unknown config key {key}, did you mean {KEY_PREFIX}{field}?")
+            }
+            None => bail!("Unknown config key {key}"),
+        }
+    }
+
     pub fn auto_close_empty_spaces(&self) -> &bool {
         &self.auto_close_empty_spaces
     }
 
-    pub fn scratchpad_grid(&self) -> &String {
+    pub fn scratchpad_grid(&self) -> &Grid {
         &self.scratchpad_grid
     }
 
@@ -46,7 +159,196 @@ impl Config {
         &self.scratchpads
     }
 
+    pub fn swallow_terminals(&self) -> &Vec<String> {
+        &self.swallow_terminals
+    }
+
+    pub fn hooks(&self) -> &Vec<Hook> {
+        &self.hooks
+    }
+
+    pub fn hook_concurrency(&self) -> &usize {
+        &self.hook_concurrency
+    }
+
+    pub fn space_labels(&self) -> &Vec<String> {
+        &self.space_labels
+    }
+
+    pub fn space_profile(&self, name: &str) -> Option<&SpaceProfile> {
+        self.space_profiles.get(name)
+    }
+
+    pub fn max_windows(&self) -> Option<usize> {
+        self.max_windows
+    }
+
+    pub fn follow_overflow(&self) -> &bool {
+        &self.follow_overflow
+    }
+
+    pub fn focus_restore(&self) -> &FocusRestore {
+        &self.focus_restore
+    }
+
+    pub fn is_scratchpad(&self, window: &Window) -> bool {
+        self.scratchpads.iter().any(|sp| sp.matcher.matches(window))
+    }
+
     pub fn scratchpad_by_tag(&self, tag: &str) -> Option<&Scratchpad> {
         self.scratchpads.iter().find(|sp| sp.tag == tag)
     }
 }
+
+/// Strategy used to restore focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusRestore {
+    /// Window under the mouse, otherwise the most recently used one.
+    Mouse,
+    /// Most recently used window.
+    Mru,
+    /// Leave focus as is.
+    Off,
+}
+
+/// yabai grid: `<rows>:<cols>:<start-x>:<start-y>:<width>:<height>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "String", into = "String")]
+#[schemars(extend("pattern" = r"^\d+:\d+:\d+:\d+:\d+:\d+$"))]
+pub struct Grid {
+    pub rows: u32,
+    pub cols: u32,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Grid {
+    pub fn new(rows: u32, cols: u32, x: u32, y: u32, w: u32, h: u32) -> Result<Self> {
+        if rows == 0 || cols == 0 {
+            bail!("Grid rows and cols must be greater than zero");
+        }
+        if w == 0 || h == 0 {
+            bail!("Grid width and height must be greater than zero");
+        }
+        if x.checked_add(w).is_none_or(|end| end > cols) {
+            bail!("Grid start-x ({x}) + width ({w}) exceeds cols ({cols})");
+        }
+        if y.checked_add(h).is_none_or(|end| end > rows) {
+            bail!("Grid start-y ({y}) + height ({h}) exceeds rows ({rows})");
+        }
+        Ok(Self {
+            rows,
+            cols,
+            x,
+            y,
+            w,
+            h,
+        })
+    }
+}
+
+impl FromStr for Grid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts = s
+            .split(':')
+            .map(|p| p.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("Invalid grid {s}, expected <rows>:<cols>:<x>:<y>:<w>:<h>"))?;
+
+        match parts[..] {
+            [rows, cols, x, y, w, h] => Self::new(rows, cols, x, y, w, h),
+            _ => bail!("Invalid grid {s}, expected <rows>:<cols>:<x>:<y>:<w>:<h>"),
+        }
+    }
+}
+
+impl TryFrom<String> for Grid {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Grid> for String {
+    fn from(grid: Grid) -> Self {
+        grid.to_string()
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            rows,
+            cols,
+            x,
+            y,
+            w,
+            h,
+        } = self;
+        write!(f, "{rows}:{cols}:{x}:{y}:{w}:{h}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_grid() {
+        let grid = "6:4:1:1:2:4".parse::<Grid>().unwrap();
+        assert_eq!(grid, Grid::new(6, 4, 1, 1, 2, 4).unwrap());
+        assert_eq!(grid.to_string(), "6:4:1:1:2:4");
+
+        assert!("6:4:1:1:2".parse::<Grid>().is_err());
+        assert!("6:4:a:1:2:4".parse::<Grid>().is_err());
+        assert!("0:4:0:0:1:1".parse::<Grid>().is_err());
+        assert!("6:4:0:0:0:1".parse::<Grid>().is_err());
+    }
+
+    #[test]
+    fn grid_out_of_bounds() {
+        assert!("6:4:3:0:2:1".parse::<Grid>().is_err());
+        assert!("6:4:0:5:1:2".parse::<Grid>().is_err());
+        assert!("1:1:4294967295:0:1:1".parse::<Grid>().is_err());
+        assert!("1:1:0:4294967295:1:1".parse::<Grid>().is_err());
+    }
+
+    #[test]
+    fn set_and_get() {
+        let mut config = Config::default();
+        config.set("yctrl_max_windows", "3").unwrap();
+        config.set("focus_restore", "mru").unwrap();
+        config.set("yctrl_scratchpad_grid", "4:4:0:0:2:2").unwrap();
+
+        assert_eq!(config.get("max_windows").unwrap(), "3");
+        assert_eq!(config.get("yctrl_focus_restore").unwrap(), "mru");
+        assert_eq!(config.get("scratchpad_grid").unwrap(), "4:4:0:0:2:2");
+
+        // Invalid values leave the configuration untouched.
+        assert!(config.set("scratchpad_grid", "4:4:3:0:2:2").is_err());
+        assert!(config.set("focus_restore", "sometimes").is_err());
+        assert_eq!(config.get("scratchpad_grid").unwrap(), "4:4:0:0:2:2");
+    }
+
+    #[test]
+    fn suggest_unknown_keys() {
+        let error = Config::default().get("yctrl_max_window").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown config key yctrl_max_window, did you mean yctrl_max_windows?"
+        );
+
+        let error = Config::default().get("yctrl_nothing_like_it").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown config key yctrl_nothing_like_it"
+        );
+        assert!(Config::parse("{ max_window: 3 }").is_err());
+    }
+}
@@ -1,3 +1,8 @@
+use std::time::Duration;
+
+/// Time given to yabai to create a space, which waits for the space animation.
+pub const SPACE_CREATE_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub const WINDOW_CREATED: &[u8; 14] = b"window_created";
 pub const WINDOW_DESTROYED: &[u8; 16] = b"window_destroyed";
 pub const WINDOW_FOCUSED: &[u8; 14] = b"window_focused";
@@ -7,6 +12,8 @@ pub const WINDOW_MINIMIZED: &[u8; 16] = b"window_minimized";
 pub const WINDOW_DEMINIMIZED: &[u8; 18] = b"window_deminimized";
 // pub const WINDOW_TITLE_CHANGED: &[u8; 20] = b"window_title_changed";
 
+pub const SPACE_CREATED: &[u8; 13] = b"space_created";
+pub const SPACE_DESTROYED: &[u8; 15] = b"space_destroyed";
 pub const SPACE_CHANGED: &[u8; 13] = b"space_changed";
 
 pub const DISPLAY_ADDED: &[u8; 13] = b"display_added";
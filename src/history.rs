@@ -0,0 +1,351 @@
+use crate::yabai::{
+    Command, Socket, Space, SpaceAction, SpaceSelector, Window, WindowAction, WindowSelector,
+};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Time after which repeated `focus mru` invocations start a new cycle.
+const CYCLE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// Most recently used windows, most recent first, across all spaces and per space.
+///
+/// Per space stacks are kept by space id, so they survive indices shifting. A window belongs
+/// to the stack of the space it was last focused in.
+#[derive(Debug, Default)]
+pub struct FocusHistory {
+    windows: Vec<u32>,
+    spaces: HashMap<u32, Vec<u32>>,
+    cycle: Option<Cycle>,
+}
+
+/// Ongoing alt-tab style cycle, during which the history isn't reordered.
+#[derive(Debug)]
+struct Cycle {
+    position: usize,
+    at: Instant,
+}
+
+impl FocusHistory {
+    /// Record that the window with the given id got focused in the space with the given id.
+    pub fn focused(&mut self, id: u32, space_id: u32) {
+        if let Some(cycle) = &self.cycle {
+            if self.windows.get(cycle.position) == Some(&id) {
+                return;
+            }
+        }
+        self.commit();
+        move_to_front(&mut self.windows, id);
+        // The window may have been moved from another space since it was last focused.
+        for stack in self.spaces.values_mut() {
+            stack.retain(|w| *w != id);
+        }
+        move_to_front(self.spaces.entry(space_id).or_default(), id);
+    }
+
+    /// Forget the window with the given id, e.g. when it's destroyed or minimized.
+    pub fn remove(&mut self, id: u32) {
+        self.commit();
+        self.windows.retain(|w| *w != id);
+        for stack in self.spaces.values_mut() {
+            stack.retain(|w| *w != id);
+        }
+        self.spaces.retain(|_, stack| !stack.is_empty());
+    }
+
+    /// Forget the stack of the space with the given id, e.g. when it's destroyed.
+    pub fn remove_space(&mut self, space_id: u32) {
+        self.spaces.remove(&space_id);
+    }
+
+    /// Id of the focused window as far as the history knows, following an ongoing cycle.
+    pub fn current(&self) -> Option<u32> {
+        let position = self.cycle.as_ref().map_or(0, |c| c.position);
+        self.windows.get(position).copied()
+    }
+
+    /// Ids of the given windows ordered by most recent use.
+    ///
+    /// Windows that were never focused come last, in their original order.
+    pub fn sort(&self, windows: &[Window]) -> Vec<u32> {
+        sort(&[&self.windows], windows)
+    }
+
+    /// Ids of the given windows ordered by most recent use in the space with the given id.
+    ///
+    /// Windows last focused in another space follow, by most recent use, then the ones that
+    /// were never focused, in their original order.
+    pub fn sort_in(&self, space_id: u32, windows: &[Window]) -> Vec<u32> {
+        let stack = self.spaces.get(&space_id).map_or(&[][..], |s| &s[..]);
+        sort(&[stack, &self.windows], windows)
+    }
+
+    /// Focus the previously focused window.
+    pub async fn focus_back(&mut self, yabai: &Socket) -> Result<()> {
+        let candidates = self.back_candidates();
+        self.focus_first(yabai, candidates).await.map(|_| ())
+    }
+
+    /// Focus the next (or previous) window in the history, continuing the current cycle if
+    /// invoked shortly after the previous one.
+    pub async fn focus_mru(&mut self, yabai: &Socket, forward: bool) -> Result<()> {
+        let position = self.cycle_position();
+        if self.windows.len() < 2 {
+            bail!("No other window to focus");
+        }
+
+        let candidates = self.cycle_candidates(position, forward);
+        let id = self.focus_first(yabai, candidates).await?;
+        self.cycled_to(id);
+        Ok(())
+    }
+
+    /// Focus the first window that can be focused, forgetting the ones that can't.
+    pub async fn focus_first(&mut self, yabai: &Socket, candidates: Vec<u32>) -> Result<u32> {
+        for id in candidates {
+            let focus = WindowAction::Focus(WindowSelector::Id(id));
+            if yabai.run(&Command::window(focus)).await.is_ok() {
+                return Ok(id);
+            }
+            tracing::debug!("Unable to focus {id}, removing it from history");
+            self.remove(id);
+        }
+        bail!("No window in history to focus")
+    }
+
+    /// Windows to try focusing to go back, ending the ongoing cycle.
+    fn back_candidates(&mut self) -> Vec<u32> {
+        self.commit();
+        self.windows.iter().skip(1).copied().collect()
+    }
+
+    /// Position the next cycle step starts from: the ongoing cycle's if still running,
+    /// otherwise the top of the history.
+    fn cycle_position(&mut self) -> usize {
+        match self.cycle.take() {
+            Some(cycle) if cycle.at.elapsed() < CYCLE_TIMEOUT => cycle.position,
+            cycle => {
+                self.cycle = cycle;
+                self.commit();
+                0
+            }
+        }
+    }
+
+    /// Windows to try focusing when cycling from `position`, wrapping around the history.
+    fn cycle_candidates(&self, position: usize, forward: bool) -> Vec<u32> {
+        let len = self.windows.len();
+        (1..len)
+            .map(|step| {
+                if forward {
+                    (position + step) % len
+                } else {
+                    (position + len - step) % len
+                }
+            })
+            .map(|idx| self.windows[idx])
+            .collect()
+    }
+
+    /// Continue the cycle from the window with the given id.
+    fn cycled_to(&mut self, id: u32) {
+        if let Some(position) = self.windows.iter().position(|w| *w == id) {
+            self.cycle = Some(Cycle {
+                position,
+                at: Instant::now(),
+            });
+        }
+    }
+
+    /// End the ongoing cycle, moving the window it stopped at to the top.
+    fn commit(&mut self) {
+        if let Some(cycle) = self.cycle.take() {
+            if cycle.position < self.windows.len() {
+                let id = self.windows.remove(cycle.position);
+                self.windows.insert(0, id);
+                if let Some(stack) = self.spaces.values_mut().find(|s| s.contains(&id)) {
+                    move_to_front(stack, id);
+                }
+            }
+        }
+    }
+}
+
+fn move_to_front(stack: &mut Vec<u32>, id: u32) {
+    stack.retain(|w| *w != id);
+    stack.insert(0, id);
+}
+
+/// Ids of `windows` in the order they first appear in `stacks`, followed by the ones found
+/// in none, in their original order.
+fn sort(stacks: &[&[u32]], windows: &[Window]) -> Vec<u32> {
+    let mut ids = vec![];
+    let ordered = stacks.iter().flat_map(|s| s.iter().copied());
+    for id in ordered.chain(windows.iter().map(|w| w.id)) {
+        if !ids.contains(&id) && windows.iter().any(|w| w.id == id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Most recently focused spaces by id, most recent first.
+///
+/// Ids are used rather than indices, as indices shift when spaces are destroyed.
+#[derive(Debug, Default)]
+pub struct SpaceHistory {
+    spaces: Vec<u32>,
+}
+
+impl SpaceHistory {
+    /// Record a space change.
+    pub fn changed(&mut self, space_id: u32, recent_space_id: u32) {
+        if self.spaces.is_empty() {
+            self.spaces.push(recent_space_id);
+        }
+        move_to_front(&mut self.spaces, space_id);
+    }
+
+    /// Forget the space with the given id, e.g. when it's destroyed.
+    pub fn remove(&mut self, id: u32) {
+        self.spaces.retain(|s| *s != id);
+    }
+
+    /// Focus the space visited `n` changes ago.
+    pub async fn focus_back(&mut self, yabai: &Socket, n: usize) -> Result<()> {
+        let spaces = yabai.spaces("all").await?;
+        let Some(space) = self.back(&spaces, n) else {
+            bail!("No space {n} steps back in history");
+        };
+
+        let focus = SpaceAction::Focus(SpaceSelector::Index(space.index));
+        yabai.run(&Command::space(focus)).await
+    }
+
+    /// Space visited `n` changes ago among existing `spaces`, forgetting the ones that are
+    /// gone.
+    fn back<'a>(&mut self, spaces: &'a [Space], n: usize) -> Option<&'a Space> {
+        self.spaces.retain(|id| spaces.iter().any(|s| &s.id == id));
+        self.spaces
+            .get(n)
+            .and_then(|id| spaces.iter().find(|s| &s.id == id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32) -> Window {
+        Window {
+            id,
+            ..Default::default()
+        }
+    }
+
+    fn space(id: u32, index: u32) -> Space {
+        Space {
+            id,
+            index,
+            ..Default::default()
+        }
+    }
+
+    /// History of windows focused in order, all in space 1.
+    fn history(ids: &[u32]) -> FocusHistory {
+        let mut history = FocusHistory::default();
+        for id in ids {
+            history.focused(*id, 1);
+        }
+        history
+    }
+
+    #[test]
+    fn cycle_wraps_around() {
+        let history = history(&[1, 2, 3]);
+
+        assert_eq!(history.cycle_candidates(0, true), [2, 1]);
+        assert_eq!(history.cycle_candidates(0, false), [1, 2]);
+        assert_eq!(history.cycle_candidates(2, true), [3, 2]);
+        assert_eq!(history.cycle_candidates(2, false), [2, 3]);
+    }
+
+    #[test]
+    fn cycle_commits_once_done() {
+        let mut history = history(&[1, 2, 3]);
+
+        let position = history.cycle_position();
+        history.cycled_to(history.cycle_candidates(position, true)[0]);
+        // Focus events of the cycle don't reorder the history.
+        history.focused(2, 1);
+        assert_eq!(history.current(), Some(2));
+        let position = history.cycle_position();
+        history.cycled_to(history.cycle_candidates(position, true)[0]);
+        assert_eq!(history.current(), Some(1));
+        assert_eq!(history.windows, [3, 2, 1]);
+
+        // Focusing another window ends the cycle where it stopped.
+        history.focused(4, 1);
+        assert_eq!(history.windows, [4, 1, 3, 2]);
+        assert_eq!(history.spaces[&1], [4, 1, 3, 2]);
+    }
+
+    #[test]
+    fn cycle_times_out() {
+        let mut history = history(&[1, 2, 3]);
+        history.cycled_to(1);
+        history.cycle.as_mut().unwrap().at -= CYCLE_TIMEOUT;
+
+        assert_eq!(history.cycle_position(), 0);
+        assert_eq!(history.windows, [1, 3, 2]);
+    }
+
+    #[test]
+    fn focus_back_ends_cycle() {
+        let mut history = history(&[1, 2, 3]);
+        assert_eq!(history.back_candidates(), [2, 1]);
+
+        history.cycled_to(1);
+        assert_eq!(history.back_candidates(), [3, 2]);
+        assert_eq!(history.current(), Some(1));
+    }
+
+    #[test]
+    fn sort_per_space() {
+        let mut history = FocusHistory::default();
+        history.focused(1, 1);
+        history.focused(2, 2);
+        history.focused(3, 1);
+        // Moved from space 1 to space 2, then focused there.
+        history.focused(1, 2);
+        let windows = [window(5), window(3), window(2), window(1)];
+
+        assert_eq!(history.sort(&windows), [1, 3, 2, 5]);
+        assert_eq!(history.sort_in(1, &windows), [3, 1, 2, 5]);
+        assert_eq!(history.sort_in(2, &windows), [1, 2, 3, 5]);
+        assert_eq!(history.sort_in(3, &windows[..2]), [3, 5]);
+
+        history.remove(3);
+        assert!(!history.spaces.contains_key(&1));
+        history.remove_space(2);
+        assert_eq!(history.sort_in(2, &windows), [1, 2, 5, 3]);
+    }
+
+    #[test]
+    fn space_history() {
+        let mut history = SpaceHistory::default();
+        history.changed(2, 1);
+        history.changed(3, 2);
+        history.changed(1, 3);
+        assert_eq!(history.spaces, [1, 3, 2]);
+
+        // Space 3 got destroyed, 2 shifted to index 2.
+        let spaces = [space(1, 1), space(2, 2)];
+        assert_eq!(history.back(&spaces, 1).map(|s| s.index), Some(2));
+        assert_eq!(history.spaces, [1, 2]);
+        assert!(history.back(&spaces, 2).is_none());
+
+        history.remove(2);
+        assert_eq!(history.spaces, [1]);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::matcher::Matcher;
+use crate::state::SharedState;
+use crate::trace;
+use crate::yabai::{Event, Socket, Window, EVENT_NAMES};
+use anyhow::{bail, Context, Error, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+
+/// Time after which a hook is killed, unless configured otherwise.
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hook definition as written by the user.
+#[derive(Deserialize, JsonSchema)]
+struct HookDef {
+    on: String,
+    #[serde(rename = "match")]
+    matcher: Option<Matcher>,
+    run: Vec<String>,
+    timeout: Option<u64>,
+}
+
+/// Command run after the daemon handled an event.
+///
+/// The event is passed as `YCTRL_*` environment variables and as JSON on stdin.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "HookDef")]
+pub struct Hook {
+    /// Event name, e.g. `window_created` or `space_changed`.
+    pub on: String,
+    /// Only run for windows matching, window events only.
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<Matcher>,
+    /// Command to run.
+    pub run: Vec<String>,
+    /// Seconds after which the command is killed, defaults to 10.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl TryFrom<HookDef> for Hook {
+    type Error = Error;
+
+    fn try_from(def: HookDef) -> Result<Self> {
+        if !EVENT_NAMES.contains(&def.on.as_str()) {
+            bail!(
+                "Unknown event {}, expected one of {}",
+                def.on,
+                EVENT_NAMES.join(", ")
+            );
+        }
+        if let Some(matcher) = &def.matcher {
+            if !def.on.starts_with("window_") {
+                bail!("{}: match is only supported on window events", def.on);
+            }
+            matcher.validate()?;
+        }
+        if def.run.is_empty() {
+            bail!("{}: run can't be empty", def.on);
+        }
+
+        Ok(Self {
+            on: def.on,
+            matcher: def.matcher,
+            run: def.run,
+            timeout: def.timeout,
+        })
+    }
+}
+
+/// Runs hooks in the background, limiting how many run at once.
+#[derive(Debug, Default)]
+pub struct HookRunner {
+    /// Permits along with the limit they were created for.
+    permits: Option<(usize, Arc<Semaphore>)>,
+}
+
+impl HookRunner {
+    fn permits(&mut self, limit: usize) -> Arc<Semaphore> {
+        match &self.permits {
+            Some((current, permits)) if *current == limit => permits.clone(),
+            _ => {
+                let permits = Arc::new(Semaphore::new(limit.max(1)));
+                self.permits = Some((limit, permits.clone()));
+                permits
+            }
+        }
+    }
+}
+
+/// Run hooks configured for `event`.
+///
+/// Hooks are spawned in the background, failures are logged.
+pub async fn trigger(state: &SharedState, event: &Event) -> Result<()> {
+    let (hooks, permits) = {
+        let mut state = state.lock().await;
+        let hooks = state
+            .config
+            .hooks()
+            .iter()
+            .filter(|h| h.on == event.name())
+            .cloned()
+            .collect::<Vec<_>>();
+        let limit = *state.config.hook_concurrency();
+        (hooks, state.hook_runner.permits(limit))
+    };
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    // NOTE: Destroyed windows can't be queried, hooks with a matcher won't run for them.
+    let window = match event.ids().iter().find(|(name, _)| *name == "window_id") {
+        Some((_, id)) => Socket::new()?.window(id).await.ok(),
+        None => None,
+    };
+
+    // Queried above as when recording, so that replays send the same yabai requests.
+    if trace::replaying() {
+        return Ok(());
+    }
+
+    let payload = payload(event, window.as_ref());
+    for hook in hooks {
+        let matches = match (&hook.matcher, &window) {
+            (None, _) => true,
+            (Some(matcher), Some(window)) => matcher.matches(window),
+            (Some(_), None) => false,
+        };
+        if !matches {
+            continue;
+        }
+
+        let permits = permits.clone();
+        let payload = payload.clone();
+        tokio::spawn(async move {
+            let Ok(_permit) = permits.acquire().await else {
+                return;
+            };
+            if let Err(e) = run(&hook, &payload).await {
+                tracing::error!("Hook {} {:?} failed: {:?}", hook.on, hook.run, e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Event as passed to hooks.
+fn payload(event: &Event, window: Option<&Window>) -> Value {
+    let mut payload = json!({ "event": event.name() });
+    for (name, id) in event.ids() {
+        payload[name] = json!(id);
+    }
+    if let Some(window) = window {
+        payload["window"] = json!(window);
+    }
+    payload
+}
+
+async fn run(hook: &Hook, payload: &Value) -> Result<()> {
+    let Some((program, args)) = hook.run.split_first() else {
+        bail!("Missing command");
+    };
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("YCTRL_EVENT", hook.on.as_str())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Value::Object(fields) = payload {
+        for (name, value) in fields.iter().filter(|(_, v)| v.is_u64()) {
+            command.env(format!("YCTRL_{}", name.to_uppercase()), value.to_string());
+        }
+    }
+
+    let mut child = command.spawn().context("Unable to spawn")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The hook may not read its stdin.
+        stdin.write_all(payload.to_string().as_bytes()).await.ok();
+    }
+
+    let duration = hook.timeout.map_or(HOOK_TIMEOUT, Duration::from_secs);
+    let output = timeout(duration, child.wait_with_output())
+        .await
+        .with_context(|| format!("Timed out after {duration:?}"))??;
+    if !output.status.success() {
+        bail!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    tracing::debug!("Hook {} {:?} succeeded", hook.on, hook.run);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hook() {
+        let hook: Hook =
+            json5::from_str(r#"{on: "window_created", match: {app: "Slack"}, run: ["true"]}"#)
+                .unwrap();
+        assert_eq!(hook.matcher.unwrap().app.unwrap().as_str(), "Slack");
+
+        for invalid in [
+            r#"{on: "window_create", run: ["true"]}"#,
+            r#"{on: "space_changed", match: {app: "Slack"}, run: ["true"]}"#,
+            r#"{on: "window_created", run: []}"#,
+        ] {
+            assert!(json5::from_str::<Hook>(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::constants::SPACE_CREATE_TIMEOUT;
+use crate::runtime;
+use crate::yabai::{Command, Socket, Space, SpaceAction, SpaceSelector, SPACE_KEYWORDS};
+use anyhow::Result;
+
+/// Configured space labels, fetched from the runtime.
+///
+/// Returns an empty list when the runtime isn't running.
+pub async fn configured() -> Vec<String> {
+    let args = ["config", "--get", "yctrl_space_labels"].map(String::from);
+    match runtime::request(&args).await {
+        Ok(labels) => serde_json::from_str(&labels).unwrap_or_default(),
+        Err(_) => vec![],
+    }
+}
+
+/// Label spaces by index, i.e. the first label goes to the first space and so on.
+///
+/// Labels found on the wrong space, e.g. after a space got destroyed and indices shifted,
+/// are moved to the right one.
+pub async fn apply(yabai: &Socket, labels: &[String]) -> Result<()> {
+    if labels.is_empty() {
+        return Ok(());
+    }
+
+    let spaces = yabai.spaces("all").await?;
+    for command in relabel(labels, &spaces) {
+        yabai.run(&command).await?;
+    }
+    Ok(())
+}
+
+/// Make sure a space labeled `label` exists, creating spaces up to its index if needed.
+///
+/// Spaces are created on the display of the focused space. Does nothing when `label` isn't
+/// a configured label, besides warning when no space has it and it looks like a typo.
+pub async fn ensure(yabai: &Socket, space: &SpaceSelector) -> Result<()> {
+    let SpaceSelector::Label(label) = space else {
+        return Ok(());
+    };
+
+    let labels = configured().await;
+    let spaces = yabai.spaces("all").await?;
+    if spaces.iter().any(|s| &s.label == label) {
+        return Ok(());
+    }
+    let Some(position) = labels.iter().position(|l| l == label) else {
+        // yabai rejects the unknown label, help spot a typo.
+        if let Some(similar) = similar(label, &labels) {
+            tracing::warn!("No space labeled {label}, did you mean {similar}?");
+        }
+        return Ok(());
+    };
+
+    let display = yabai.focused_space().await?.display;
+    for _ in 0..missing_spaces(&spaces, display, position) {
+        yabai
+            .with_timeout(SPACE_CREATE_TIMEOUT)
+            .run(&Command::space(SpaceAction::Create))
+            .await?;
+    }
+
+    apply(yabai, &labels).await
+}
+
+/// Commands labeling `spaces` by index.
+fn relabel(labels: &[String], spaces: &[Space]) -> Vec<Command> {
+    // Clear misplaced labels first, as yabai requires labels to be unique.
+    let unlabel = spaces
+        .iter()
+        .filter(|s| labels.contains(&s.label) && label_at(labels, s.index) != Some(&s.label))
+        .map(|s| Command::space_at(SpaceSelector::Index(s.index), SpaceAction::Unlabel));
+    let label = spaces.iter().filter_map(|s| {
+        let label = label_at(labels, s.index).filter(|l| *l != &s.label)?;
+        tracing::debug!("Labeling space {} as {label}", s.index);
+        let action = SpaceAction::Label(label.clone());
+        Some(Command::space_at(SpaceSelector::Index(s.index), action))
+    });
+    unlabel.chain(label).collect()
+}
+
+/// Spaces to create on `display` for a space to exist at the (0-based) `position`.
+///
+/// yabai inserts created spaces after the last space of the display, shifting the indices of
+/// the following displays, so the count depends on that display rather than on all spaces.
+fn missing_spaces(spaces: &[Space], display: u32, position: usize) -> usize {
+    let index = position as u32 + 1;
+    if spaces.iter().any(|s| s.index == index) {
+        return 0;
+    }
+    let last = spaces
+        .iter()
+        .filter(|s| s.display == display)
+        .map(|s| s.index)
+        .max()
+        .unwrap_or_default();
+    index.saturating_sub(last) as usize
+}
+
+/// Configured label or space selector keyword `label` is most likely a typo of.
+fn similar<'a>(label: &str, labels: &'a [String]) -> Option<&'a str> {
+    labels
+        .iter()
+        .map(String::as_str)
+        .chain(SPACE_KEYWORDS)
+        .map(|candidate| (strsim::osa_distance(label, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Label configured for the space at the given (1-based) index.
+fn label_at(labels: &[String], index: u32) -> Option<&String> {
+    (index as usize).checked_sub(1).and_then(|i| labels.get(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space(index: u32, display: u32, label: &str) -> Space {
+        Space {
+            id: index * 10,
+            index,
+            display,
+            label: label.into(),
+            ..Default::default()
+        }
+    }
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn move_shifted_labels() {
+        // Space 2 ("code") got destroyed, "chat" shifted to index 2.
+        let spaces = [space(1, 1, "web"), space(2, 1, "chat"), space(3, 1, "")];
+        let commands = relabel(&labels(&["web", "code", "chat"]), &spaces)
+            .iter()
+            .map(Command::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            commands,
+            [
+                "space 2 --label",
+                "space 2 --label code",
+                "space 3 --label chat"
+            ]
+        );
+    }
+
+    #[test]
+    fn leave_labeled_spaces_alone() {
+        let spaces = [
+            space(1, 1, "web"),
+            space(2, 1, "code"),
+            space(3, 1, "other"),
+        ];
+        assert!(relabel(&labels(&["web", "code"]), &spaces).is_empty());
+    }
+
+    #[test]
+    fn create_missing_spaces_on_target_display() {
+        let spaces = [
+            space(1, 1, ""),
+            space(2, 1, ""),
+            space(3, 2, ""),
+            space(4, 2, ""),
+            space(5, 2, ""),
+        ];
+
+        assert_eq!(missing_spaces(&spaces, 1, 4), 0);
+        // Index 6 follows the last space of display 2.
+        assert_eq!(missing_spaces(&spaces, 2, 5), 1);
+        // Spaces created on display 1 get indices 3 to 6, pushing display 2 back.
+        assert_eq!(missing_spaces(&spaces, 1, 5), 4);
+        assert_eq!(missing_spaces(&spaces[..2], 1, 2), 1);
+    }
+
+    #[test]
+    fn suggest_similar_labels() {
+        let labels = labels(&["web", "code", "chat"]);
+
+        assert_eq!(similar("nxet", &labels), Some("next"));
+        assert_eq!(similar("cdoe", &labels), Some("code"));
+        assert_eq!(similar("music", &labels), None);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::cli::{LogArgs, LogFormat, LogRotation};
+use anyhow::{Context, Result};
+use std::env;
+use std::path::Path;
+use tracing::Subscriber;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, registry, reload, EnvFilter, Layer, Registry};
+
+/// Handle changing the log filter of the daemon.
+pub type FilterHandle = reload::Handle<EnvFilter, Registry>;
+
+const LOG_DIR: &str = "/tmp";
+const LOG_FILE: &str = "yctrl.log";
+/// Rotated log files kept, unless configured otherwise.
+const LOG_RETENTION: usize = 7;
+/// Filter used when neither `--log-level` nor `RUST_LOG` are set.
+const DEFAULT_FILTER: &str = "info";
+
+/// Parse filter directives such as `info,yctrl::runtime=debug`.
+pub fn filter(directives: &str) -> Result<EnvFilter> {
+    EnvFilter::builder()
+        .parse(directives)
+        .with_context(|| format!("Invalid log filter {directives}"))
+}
+
+/// Log daemon diagnostics to a file, and stdout unless disabled.
+pub fn configure(args: &LogArgs) -> Result<FilterHandle> {
+    let directives = match &args.log_level {
+        Some(directives) => directives.clone(),
+        None => env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| DEFAULT_FILTER.into()),
+    };
+    let (filter, handle) = reload::Layer::new(filter(&directives)?);
+
+    let rotation = match args.log_rotation {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+    let dir = args.log_dir.as_deref().unwrap_or(Path::new(LOG_DIR));
+    let file = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(LOG_FILE)
+        .max_log_files(args.log_retention.unwrap_or(LOG_RETENTION))
+        .build(dir)
+        .with_context(|| format!("Unable to log to {}", dir.display()))?;
+
+    let mut layers = vec![layer(file, args, false)];
+    if !args.no_log_stdout {
+        layers.push(layer(std::io::stdout, args, true));
+    }
+
+    registry()
+        .with(filter)
+        .with(layers)
+        .try_init()
+        .context("Unable to configure logging")?;
+    Ok(handle)
+}
+
+/// Layer writing to `writer` in the configured format.
+fn layer<S, W>(writer: W, args: &LogArgs, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_line_number(true);
+    match (args.log_format, args.log_timestamps) {
+        (LogFormat::Text, true) => layer.with_target(false).boxed(),
+        (LogFormat::Text, false) => layer.with_target(false).without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().boxed(),
+        (LogFormat::Json, false) => layer.json().without_time().boxed(),
+    }
+}
@@ -1,248 +1,508 @@
+mod cli;
 mod config;
 mod constants;
+mod history;
+mod hook;
+mod label;
+mod logging;
+mod matcher;
+mod metrics;
+mod output;
+mod profile;
 mod runtime;
 mod scratchpad;
+mod selector;
+mod stack;
 mod state;
+mod subscription;
+mod trace;
 mod util;
 mod yabai;
 
-use anyhow::{anyhow, bail, Result};
-use std::env;
-use std::fmt::Debug;
-
-fn should_just_redirect<A>(cmd: &str, _args: &[A]) -> bool
-where
-    A: AsRef<[u8]> + Debug,
-{
-    cmd != "focus"
-        && cmd != "swap"
-        && cmd != "move"
-        && cmd != "warp"
-        && cmd != "space"
-        && cmd != "inc"
-        && cmd != "make"
+use anyhow::{anyhow, bail, Context, Result};
+use clap::CommandFactory;
+use cli::{
+    Cli, Cmd, ConfigArgs, DaemonArgs, DaemonCmd, Side, SpaceArgs, SpaceCmd, SpaceFocus, WindowArgs,
+    WindowCmd, WindowFocus,
+};
+use matcher::Matcher;
+use output::{Format, Query};
+use selector::SpaceTarget;
+use std::time::Duration;
+use std::{env, io};
+use yabai::{Command, ResizeHandle, SpaceAction, SpaceSelector, WindowAction, WindowSelector};
+
+/// Arguments as expected by yabai, i.e. with the command prefixed by `--`.
+fn yabai_args(domain: &str, target: Option<u32>, args: &[String]) -> Vec<String> {
+    let mut yabai_args = vec![domain.to_string()];
+    yabai_args.extend(target.map(|t| t.to_string()));
+    if let Some((command, rest)) = args.split_first() {
+        match command.starts_with('-') {
+            true => yabai_args.push(command.clone()),
+            false => yabai_args.push(format!("--{command}")),
+        }
+        yabai_args.extend(rest.iter().cloned());
+    }
+    yabai_args
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let mut args: Vec<String> = env::args().skip(1).collect();
-    let argc = args.len();
-
-    if argc == 0 {
-        return runtime::start()
-            .await
-            .map_err(|e| anyhow!("Unable to start listener: {e}"));
-    } else if argc < 2 {
-        bail!("yctrl: Not enough arguments provided.")
+    let cli = Cli::parse_args(env::args());
+    if let Some(ms) = cli.yabai_timeout {
+        yabai::set_timeout(Duration::from_millis(ms));
     }
 
-    // Get yabai scoket path
-    let yabai = yabai::Socket::new()?;
-
-    // Fix when the user provided id for sub command.
-    let mut command_pos = 1;
-    if args[1].as_str().parse::<u32>().is_ok() {
-        command_pos = 2;
+    let command = match cli.command {
+        None => Cmd::Daemon(DaemonArgs::default()),
+        Some(command) => command,
+    };
+    // The daemon configures its own logging.
+    if !matches!(command, Cmd::Daemon(DaemonArgs { command: None, .. })) {
+        configure_diagnostics(cli.verbose);
     }
 
-    if args[0].as_str() != "config" && args[0].as_str() != "scratchpad" {
-        // Correct format: Note should maybe check if it's already correct
-        let command = args.get_mut(command_pos).unwrap();
-        let cmd = command.clone();
-        *command = format!("--{command}");
-        // Check if we should just redirect to yabai scoket.
-        if should_just_redirect(&cmd, &args) {
-            println!("redircting '{:?}' to yabai socket.", args);
-            return yabai.execute(&args).await;
+    // Only commands talking to yabai need its socket (and USER to locate it).
+    let yabai = yabai::Socket::new;
+
+    // Handle User request
+    match command {
+        Cmd::Window(args) => WindowService::handle(&yabai()?, args).await,
+        Cmd::Space(args) => SpaceService::handle(&yabai()?, args).await,
+        Cmd::Scratchpad { tag } => runtime::execute(&["scratchpad", &tag]).await,
+        Cmd::Config(args) => ConfigService::handle(args).await,
+        Cmd::Query(args) => {
+            let raw = yabai()?.request(&args.yabai_args()).await?;
+            match &args.format {
+                // Printed as is, as yabai may have been asked for some properties only.
+                Format::Json => println!("{}", raw.trim_end()),
+                format => {
+                    let query = Query::parse(args.kind(), &raw, args.properties())?;
+                    println!("{}", query.render(format)?)
+                }
+            }
+            Ok(())
+        }
+        Cmd::Layout { layout } => {
+            let yabai = yabai()?;
+            let command = Command::space(SpaceAction::Layout(layout));
+            yabai.run(&command).await?;
+            runtime::notify_layout(&yabai, &command.args()).await
+        }
+        Cmd::Subscribe { events } => runtime::subscribe(&events).await,
+        Cmd::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "yctrl", &mut io::stdout());
+            Ok(())
         }
+        Cmd::Yabai(args) => {
+            let (domain, rest) = args.split_first().context("Missing yabai command")?;
+            let args = yabai_args(domain, None, rest);
+            let yabai = yabai()?;
+            yabai.execute(&args).await?;
+            runtime::notify_layout(&yabai, &args).await
+        }
+        Cmd::Replay { trace } => trace::replay(&trace).await,
+        Cmd::Daemon(args) => DaemonService::handle(args).await,
     }
+}
 
-    // Handle User request
-    match args[0].as_str() {
-        "window" => WindowService::handle(&yabai, args).await,
-        "space" => SpaceService::handle(&yabai, args).await,
-        "scratchpad" => runtime::execute(&args).await,
-        "config" => {
-            if args[1].as_str().contains("yctrl") {
-                runtime::execute(&args).await
-            } else {
-                yabai.execute(&args).await
+struct DaemonService();
+impl DaemonService {
+    async fn handle(args: DaemonArgs) -> Result<()> {
+        match args.command {
+            None => runtime::start(args)
+                .await
+                .map_err(|e| anyhow!("Unable to start listener: {e}")),
+            Some(DaemonCmd::LogLevel { filter }) => {
+                runtime::execute(&["daemon", "log-level", &filter]).await
+            }
+            Some(DaemonCmd::Metrics { prometheus }) => {
+                let mut args = vec!["daemon", "metrics"];
+                args.extend(prometheus.then_some("prometheus"));
+                let metrics = runtime::request(&args).await?;
+                println!("{}", metrics.trim_end());
+                Ok(())
             }
         }
-        _ => yabai.execute(&args).await,
     }
 }
 
+/// Print diagnostics to stderr according to verbosity.
+fn configure_diagnostics(verbose: u8) {
+    let level = match verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_max_level(level)
+        .without_time()
+        .with_target(false)
+        .init();
+}
+
 struct WindowService();
 impl WindowService {
-    async fn space(yabai: &yabai::Socket, args: Vec<String>) -> Result<()> {
-        let select = args.last().unwrap();
-        let command = args[1].clone();
-        let space_args = vec!["space".to_string(), "--focus".to_string(), select.clone()];
+    async fn handle(yabai: &yabai::Socket, args: WindowArgs) -> Result<()> {
+        // Window id provided before the command.
+        let target = args.id.map(WindowSelector::Id);
 
-        // Only further process next/prev, if not run the command as it.
-        if select != "next" && select != "prev" && yabai.execute(&args).await.is_ok() {
-            return SpaceService::handle(yabai, space_args).await;
+        match args.command {
+            WindowCmd::Focus {
+                target: WindowFocus::Back,
+                ..
+            } => runtime::execute(&["window", "focus", "back"]).await,
+            WindowCmd::Focus {
+                target: WindowFocus::Mru,
+                direction,
+            } => {
+                let direction = match direction {
+                    Some(cli::Cycle::Prev) => "prev",
+                    _ => "next",
+                };
+                runtime::execute(&["window", "focus", "mru", direction]).await
+            }
+            WindowCmd::Focus {
+                target: WindowFocus::Select(select),
+                ..
+            } => {
+                let select = select.resolve(yabai).await?;
+                Self::cycle(yabai, target, WindowAction::Focus, select).await
+            }
+            WindowCmd::Swap { selector } => {
+                let select = selector.resolve(yabai).await?;
+                Self::cycle(yabai, target, WindowAction::Swap, select).await
+            }
+            WindowCmd::Warp { selector } => {
+                let select = selector.resolve(yabai).await?;
+                Self::cycle(yabai, target, WindowAction::Warp, select).await
+            }
+            WindowCmd::Space { selector } => {
+                let select = selector.resolve(yabai).await?;
+                Self::space(yabai, target, select).await
+            }
+            WindowCmd::Raise {
+                matcher,
+                timeout,
+                command,
+            } => {
+                let timeout = timeout.map_or(util::LAUNCH_TIMEOUT, Duration::from_secs);
+                Self::raise(yabai, &matcher, &command, timeout).await
+            }
+            WindowCmd::Stack { selector } => {
+                yabai
+                    .run(&Command::Window(target, WindowAction::Stack(selector)))
+                    .await
+            }
+            WindowCmd::Unstack => stack::unstack(yabai, args.id).await,
+            WindowCmd::Stacks => {
+                let snapshot = yabai.snapshot().await?;
+                println!("{}", stack::table(&stack::group(snapshot.windows())));
+                Ok(())
+            }
+            WindowCmd::Monocle => runtime::execute(&["window", "monocle"]).await,
+            WindowCmd::Inc { side } => Self::inc(yabai, side == Side::Left).await,
+            WindowCmd::Make { .. } => Self::master(yabai).await,
+            WindowCmd::Resize {
+                resize: (handle, dx, dy),
+            } => {
+                yabai
+                    .run(&Command::Window(
+                        target,
+                        WindowAction::Resize(handle, dx, dy),
+                    ))
+                    .await
+            }
+            WindowCmd::Yabai(rest) => yabai.execute(&yabai_args("window", args.id, &rest)).await,
         }
+    }
+
+    async fn space(
+        yabai: &yabai::Socket,
+        target: Option<WindowSelector>,
+        select: SpaceSelector,
+    ) -> Result<()> {
+        label::ensure(yabai, &select).await?;
+        let command = Command::Window(target, WindowAction::Space(select.clone()));
 
-        // Try to execute as is
-        if yabai.execute(&args).await.is_ok() {
-            return SpaceService::handle(yabai, space_args).await;
+        if let Err(e) = yabai.run(&command).await {
+            if !select.is_cyclic() {
+                return Err(e.context(format!("Fail handle space command!!! {command}")));
+            }
+
+            // Try position rather than order
+            let pos = match select {
+                SpaceSelector::Next => SpaceSelector::First,
+                _ => SpaceSelector::Last,
+            };
+            yabai
+                .run(&Command::Window(target, WindowAction::Space(pos)))
+                .await
+                .with_context(|| format!("Fail handle space command!!! {command}"))?;
         }
 
-        // Try position rather than order
-        let pos = if select == "next" { "first" } else { "last" };
-        if yabai.execute(&["window", &command, pos]).await.is_ok() {
-            return SpaceService::handle(yabai, space_args).await;
+        SpaceService::cycle(yabai, None, SpaceAction::Focus, select).await
+    }
+
+    /// Focus the window satisfying `matcher`, launching `command` if there's none.
+    async fn raise(
+        yabai: &yabai::Socket,
+        matcher: &Matcher,
+        command: &[String],
+        timeout: Duration,
+    ) -> Result<()> {
+        let snapshot = yabai.snapshot().await?;
+        let window = match selector::next_match(&snapshot, matcher) {
+            Some(window) => window.clone(),
+            None if command.is_empty() => bail!("No matching window and no command to run"),
+            None => util::launch(yabai, command, matcher, timeout).await?,
+        };
+
+        let to_current_space =
+            runtime::request(&["config", "--get", "yctrl_raise_to_current_space"])
+                .await
+                .is_ok_and(|value| value.trim() == "true");
+        if let Some(space) = snapshot
+            .focused_space()
+            .filter(|s| to_current_space && s.index != window.space)
+        {
+            let select = SpaceSelector::Index(space.index);
+            let command =
+                Command::window_at(WindowSelector::Id(window.id), WindowAction::Space(select));
+            yabai.run(&command).await?;
         }
 
-        bail!("Fail handle space command!!! {:?}", args)
+        let focus = WindowAction::Focus(WindowSelector::Id(window.id));
+        yabai.run(&Command::window(focus)).await
     }
 
     /// Toggle between largest and smallest window.
     /// TODO: Switch between left space and child windows
     async fn master(yabai: &yabai::Socket) -> Result<()> {
-        let succ = yabai.execute(&["window", "--warp", "first"]).await.is_ok();
-        if !succ {
-            yabai.execute(&["window", "--warp", "last"]).await?
+        let first = Command::window(WindowAction::Warp(WindowSelector::First));
+        if yabai.run(&first).await.is_err() {
+            let last = Command::window(WindowAction::Warp(WindowSelector::Last));
+            yabai.run(&last).await?
         }
         Ok(())
-
-        // let windows: Vec<YabaiWindow> = query(&socket_path, QUERY_SPACE_WINDOWS)?;
-        // let current = windows.iter().find(|w| w.has_focus).unwrap();
-        // let largest = windows.iter().max_by_key(|&w| w.frame.sum()).unwrap();
-        // eprintln!("largest = {:#?}", largest);
-        // eprintln!("total = {:#?}", largest.frame.sum());
-        // let mut partial_args = vec!["window".to_string(), "--warp".to_string()];
-        // if largest.id == current.id {
-        //     partial_args.push("next".to_string());
-        //     Self::handle(socket_path, partial_args)
-        // } else {
-        //     partial_args.push(largest.id.to_string());
-        //     Self::handle(socket_path, partial_args)
-        // }
     }
 
-    async fn inc(yabai: &yabai::Socket, args: Vec<String>) -> Result<()> {
-        let left = args.last().unwrap() == "left";
-        let dir = if left { "-150:0" } else { "+150:0" };
-        let args = &["window", "--resize", &format!("left:{dir}")];
+    async fn inc(yabai: &yabai::Socket, left: bool) -> Result<()> {
+        let dx = if left { -150 } else { 150 };
+        let resize = |handle| Command::window(WindowAction::Resize(handle, dx, 0));
 
-        if !yabai.execute(args).await.is_ok() {
-            let mut args = args.to_vec();
-            let direction = format!("right:{dir}");
-            args.insert(2, &direction);
-            yabai.execute(&args).await?
+        if yabai.run(&resize(ResizeHandle::Left)).await.is_err() {
+            yabai.run(&resize(ResizeHandle::Right)).await?
         }
         Ok(())
     }
 
-    async fn handle(yabai: &yabai::Socket, args: Vec<String>) -> Result<()> {
-        // Handle special cases
-        match (args[1].as_str(), args[2].as_str()) {
-            ("--space", _) => return Self::space(yabai, args).await,
-            ("--inc", _) => return Self::inc(yabai, args).await,
-            ("--make", "master") => return Self::master(yabai).await,
-            _ => (),
-        };
+    async fn cycle(
+        yabai: &yabai::Socket,
+        target: Option<WindowSelector>,
+        action: fn(WindowSelector) -> WindowAction,
+        select: WindowSelector,
+    ) -> Result<()> {
+        let command = Command::Window(target, action(select));
 
-        let select = args.last().unwrap().as_str();
-        let command = args[1].clone();
+        // Wrap around stacks, yabai fails past their ends.
+        if let Some(end) = stack::wrap(select) {
+            return match yabai.run(&command).await {
+                Err(e) if yabai::Error::is_rejection(&e) => {
+                    yabai.run(&Command::Window(target, action(end))).await
+                }
+                result => result,
+            };
+        }
 
         // Only further process next/prev, if not run the command as it.
-        if select != "next" && select != "prev" {
-            println!("got {select} redirecting to yabai socket");
-            return yabai.execute(&args).await;
+        if !select.is_cyclic() {
+            tracing::debug!("got {select} redirecting to yabai socket");
+            return yabai.run(&command).await;
+        }
+
+        // Go through stacked windows before moving to the next tile.
+        let is_focus = matches!(action(select), WindowAction::Focus(_));
+        let in_stacks = is_focus && target.is_none();
+        if in_stacks && stack::focus_in_stack(yabai, select).await? {
+            return Ok(());
         }
 
         // See if next/prev just works before doing anything else.
-        if yabai.execute(&args).await.is_ok() {
-            println!("successfully ran {select} through yabai socket");
+        if yabai.run(&command).await.is_ok() {
+            tracing::debug!("successfully ran {select} through yabai socket");
+            if in_stacks {
+                stack::enter_stack(yabai, select).await?;
+            }
             return Ok(());
         }
 
-        println!("Fail to run {select}, ... trying to determine next window");
+        tracing::debug!("Fail to run {select}, ... trying to determine next window");
 
         // Get current space information.
-        let space = yabai.focused_space().await?;
+        let snapshot = yabai.snapshot().await?;
+        let space = snapshot.focused_space().context("No focused space")?;
 
-        println!("Got yabai spaces");
+        tracing::debug!("Got yabai spaces");
 
-        if space.first_window == space.last_window && &command == "--focus" {
-            let windows = yabai.windows("current").await?;
-            println!("{windows:#?}");
+        if space.first_window == space.last_window && is_focus {
+            let windows = snapshot
+                .windows_in(space)
+                .filter(|w| w.is_shown())
+                .collect::<Vec<_>>();
+            tracing::trace!("{windows:#?}");
             if windows.is_empty() {
-                println!("No windows left in space, trying {select} space instead of window");
-                let args = vec!["space".to_string(), command, select.to_string()];
-                return SpaceService::handle(yabai, args).await;
-            } else if let Some(current_focused) = windows.iter().find(|w| w.has_focus) {
+                tracing::debug!(
+                    "No windows left in space, trying {select} space instead of window"
+                );
+                let select = match select {
+                    WindowSelector::Next => SpaceSelector::Next,
+                    _ => SpaceSelector::Prev,
+                };
+                return SpaceService::cycle(yabai, None, SpaceAction::Focus, select).await;
+            } else if let Some(current_focused) =
+                snapshot.focused_window().filter(|w| w.space == space.index)
+            {
                 if let Some(current_index) =
                     space.windows.iter().position(|&x| x == current_focused.id)
                 {
-                    println!("current_index: {current_index:?}");
-                    let idx = if select == "next" {
-                        let idx = current_index + 1;
-                        if idx > space.windows.len() - 1 {
-                            0
-                        } else {
-                            idx
-                        }
-                    } else {
-                        if current_index == 0 {
-                            space.windows.len() - 1
-                        } else {
-                            let idx = current_index - 1;
-                            if idx > 0 {
-                                idx
-                            } else {
-                                space.windows.len() - 1
-                            }
-                        }
+                    tracing::debug!("current_index: {current_index:?}");
+                    let len = space.windows.len();
+                    let idx = match select {
+                        WindowSelector::Next => (current_index + 1) % len,
+                        _ => (current_index + len - 1) % len,
                     };
-                    if let Some(id) = space.windows.get(idx) {
-                        let args = &["window", &command, &id.to_string()];
-                        println!("{args:?}");
-                        return yabai.execute(args).await;
-                    } else {
-                        eprintln!("{idx} is invalid index: {:?}", space.windows);
-                    }
+                    let command =
+                        Command::Window(target, action(WindowSelector::Id(space.windows[idx])));
+                    tracing::debug!("{command}");
+                    return yabai.run(&command).await;
                 };
             }
         }
 
         // Get Id based on whether the select value.
-        let id = if select == "next" {
-            space.first_window.to_string()
-        } else {
-            space.last_window.to_string()
+        let id = match select {
+            WindowSelector::Next => space.first_window,
+            _ => space.last_window,
         };
 
-        println!("{select} window isn't found, trying to foucs {id}");
+        tracing::debug!("{select} window isn't found, trying to foucs {id}");
 
         // Finally, Try to focus by id or else focus to first window
-        let succ = yabai.execute(&["window", &command, &id]).await.is_ok();
-        if !succ {
-            yabai.execute(&["window", &command, "first"]).await?
+        let by_id = Command::Window(target, action(WindowSelector::Id(id)));
+        if yabai.run(&by_id).await.is_err() {
+            let first = Command::Window(target, action(WindowSelector::First));
+            yabai.run(&first).await?
+        }
+        Ok(())
+    }
+}
+
+struct ConfigService();
+impl ConfigService {
+    async fn handle(args: ConfigArgs) -> Result<()> {
+        let is_yctrl_key = |key: &str| key.starts_with(config::KEY_PREFIX);
+
+        if args.schema {
+            println!("{}", config::Config::schema()?);
+        } else if args.list {
+            println!("{}", runtime::request(&["config", "--list"]).await?);
+        } else if let Some(key) = args.get {
+            match is_yctrl_key(&key) {
+                true => println!("{}", runtime::request(&["config", "--get", &key]).await?),
+                false => print!(
+                    "{}",
+                    yabai::Socket::new()?.request(&["config", &key]).await?
+                ),
+            }
+        } else if let Some(key) = args.key {
+            if is_yctrl_key(&key) {
+                if args.space.is_some() {
+                    bail!("{key} doesn't apply to a space");
+                }
+                let mut request = vec!["config".to_string(), key.clone()];
+                request.extend(args.value.iter().cloned());
+                match args.value.is_empty() {
+                    true => println!("{}", runtime::request(&request).await?),
+                    false => runtime::execute(&request).await?,
+                }
+            } else {
+                let mut request = vec!["config".to_string()];
+                if let Some(space) = &args.space {
+                    request.extend(["--space".to_string(), space.to_string()]);
+                }
+                request.push(key.clone());
+                request.extend(args.value.iter().cloned());
+                let yabai = yabai::Socket::new()?;
+                match args.value.is_empty() {
+                    true => print!("{}", yabai.request(&request).await?),
+                    false => yabai.execute(&request).await?,
+                }
+            }
+        } else {
+            bail!("Missing config key");
         }
+
         Ok(())
     }
 }
 
 struct SpaceService();
 impl SpaceService {
-    async fn handle(yabai: &yabai::Socket, args: Vec<String>) -> Result<()> {
-        let select = args.last().unwrap();
+    async fn handle(yabai: &yabai::Socket, args: SpaceArgs) -> Result<()> {
+        // Space index provided before the command.
+        let target = args.index.map(SpaceSelector::Index);
 
-        // Only further process when select != next/prev and succeeded
-        if select != "next" && select != "prev" && yabai.execute(&args).await.is_ok() {
-            return Ok(());
+        let (action, select): (fn(SpaceSelector) -> SpaceAction, SpaceTarget) = match args.command {
+            SpaceCmd::Focus {
+                target: SpaceFocus::Back,
+                steps,
+            } => {
+                let steps = steps.to_string();
+                return runtime::execute(&["space", "focus", "back", &steps]).await;
+            }
+            SpaceCmd::Profile { name } => {
+                return runtime::execute(&["space", "profile", &name]).await
+            }
+            SpaceCmd::Yabai(rest) => {
+                let args = yabai_args("space", args.index, &rest);
+                yabai.execute(&args).await?;
+                return runtime::notify_layout(yabai, &args).await;
+            }
+            SpaceCmd::Focus {
+                target: SpaceFocus::Select(select),
+                ..
+            } => (SpaceAction::Focus, select),
+            SpaceCmd::Move { selector } => (SpaceAction::Move, selector),
+            SpaceCmd::Swap { selector } => (SpaceAction::Swap, selector),
+        };
+
+        let select = select.resolve(yabai).await?;
+        label::ensure(yabai, &select).await?;
+        Self::cycle(yabai, target, action, select).await
+    }
+
+    async fn cycle(
+        yabai: &yabai::Socket,
+        target: Option<SpaceSelector>,
+        action: fn(SpaceSelector) -> SpaceAction,
+        select: SpaceSelector,
+    ) -> Result<()> {
+        let command = Command::Space(target.clone(), action(select.clone()));
+
+        // Only further process next/prev, if not run the command as it.
+        if !select.is_cyclic() {
+            return yabai.run(&command).await;
         }
 
         // See if next/prev just works before doing anything else.
-        let succ = yabai.execute(&args).await.is_ok();
-        if !succ {
-            let pos = if select == "next" { "first" } else { "last" };
-            yabai.execute(&["space", &args[1], pos]).await?
+        if yabai.run(&command).await.is_err() {
+            let pos = match select {
+                SpaceSelector::Next => SpaceSelector::First,
+                _ => SpaceSelector::Last,
+            };
+            yabai.run(&Command::Space(target, action(pos))).await?
         }
         Ok(())
     }
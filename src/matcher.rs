@@ -0,0 +1,331 @@
+use crate::trace;
+use crate::yabai::Window;
+use anyhow::{bail, Context, Error, Result};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::process::Command;
+use std::str::{Chars, FromStr};
+use std::sync::Mutex;
+
+/// Bundle ids of processes looked up so far, by pid.
+static BUNDLE_IDS: Mutex<Option<HashMap<u32, Option<String>>>> = Mutex::new(None);
+
+/// Characters with a special meaning outside bracket expressions, in POSIX extended regular
+/// expressions as in Rust regexes.
+const SPECIAL: &str = ".[\\()*+?{|^$";
+
+/// Criteria used to identify a window.
+///
+/// The same matcher drives both the yabai `rule --add` arguments and yctrl's own window
+/// lookups, so the two never disagree. `app` and `title` are unanchored regular
+/// expressions, like in yabai rules, while `role` and `subrole` are compared literally.
+/// `bundle_id` and `pid` can't be expressed as yabai rules and are only checked by yctrl.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Matcher {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub app: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub title: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subrole: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+}
+
+/// Regular expression limited to the syntax yabai rules (POSIX extended regular
+/// expressions) and yctrl agree on, compiled once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(Regex);
+
+impl Matcher {
+    /// Matcher for windows whose app name is exactly `app`.
+    pub fn app_exact(app: &str) -> Self {
+        Self {
+            app: Some(Pattern::exact(app)),
+            ..Default::default()
+        }
+    }
+
+    /// Matcher for windows whose title is exactly `title`.
+    pub fn title_exact(title: &str) -> Self {
+        Self {
+            title: Some(Pattern::exact(title)),
+            ..Default::default()
+        }
+    }
+
+    /// Ensure the matcher has at least one criteria.
+    pub fn validate(&self) -> Result<()> {
+        if self == &Self::default() {
+            bail!("Matcher requires at least one of app, title, role, subrole, bundle_id or pid");
+        }
+        Ok(())
+    }
+
+    /// Arguments to pass to `rule --add` so yabai matches the same windows.
+    pub fn rule_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(app) = &self.app {
+            args.push(format!("app={app}"));
+        }
+        if let Some(title) = &self.title {
+            args.push(format!("title={title}"));
+        }
+        if let Some(role) = &self.role {
+            args.push(format!("role={role}"));
+        }
+        if let Some(subrole) = &self.subrole {
+            args.push(format!("subrole={subrole}"));
+        }
+        args
+    }
+
+    /// Whether the given window satisfies every criteria of the matcher.
+    pub fn matches(&self, window: &Window) -> bool {
+        self.app.as_ref().is_none_or(|p| p.is_match(&window.app))
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|p| p.is_match(&window.title))
+            && self.role.as_ref().is_none_or(|role| role == &window.role)
+            && self
+                .subrole
+                .as_ref()
+                .is_none_or(|subrole| subrole == &window.subrole)
+            && self.pid.is_none_or(|pid| pid == window.pid)
+            && self
+                .bundle_id
+                .as_ref()
+                .is_none_or(|id| bundle_id(window.pid).as_ref() == Some(id))
+    }
+}
+
+impl Pattern {
+    /// Anchored pattern that matches `value` literally.
+    pub fn exact(value: &str) -> Self {
+        let escaped = value
+            .chars()
+            .map(|c| match SPECIAL.contains(c) {
+                true => format!("\\{c}"),
+                false => c.to_string(),
+            })
+            .collect::<String>();
+        Self(Regex::new(&format!("^{escaped}$")).expect("escaped pattern is valid"))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        check_ere(s).with_context(|| format!("Invalid matcher pattern: {s}"))?;
+        let regex = Regex::new(s).with_context(|| format!("Invalid matcher pattern: {s}"))?;
+        Ok(Self(regex))
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> Self {
+        pattern.to_string()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Reject syntax Rust regexes understand but yabai's POSIX extended regular expressions
+/// don't (or understand differently), e.g. `\d`, lazy quantifiers or lookarounds.
+fn check_ere(pattern: &str) -> Result<()> {
+    let mut chars = pattern.chars().peekable();
+    let mut quantified = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if SPECIAL.contains(escaped) => {}
+                Some(escaped) => {
+                    bail!("\\{escaped} isn't supported by yabai, only {SPECIAL} can be escaped")
+                }
+                None => bail!("Trailing backslash"),
+            },
+            '(' if chars.peek() == Some(&'?') => {
+                bail!("(? groups (flags, lookarounds...) aren't supported by yabai")
+            }
+            '?' if quantified => bail!("Lazy quantifiers aren't supported by yabai"),
+            '[' => check_bracket(&mut chars)?,
+            _ => {}
+        }
+        quantified = matches!(c, '*' | '+' | '?' | '}');
+    }
+    Ok(())
+}
+
+/// Check a bracket expression, up to and including its closing `]`.
+fn check_bracket(chars: &mut Peekable<Chars>) -> Result<()> {
+    // A leading `]` (after an optional `^`) is part of the expression.
+    chars.next_if_eq(&'^');
+    let mut previous = chars.next_if_eq(&']');
+    loop {
+        let c = chars.next().context("Unterminated bracket expression")?;
+        match c {
+            ']' => return Ok(()),
+            '\\' => bail!("Backslashes are literal in yabai bracket expressions"),
+            '[' => match chars.next() {
+                Some(kind @ (':' | '.' | '=')) => {
+                    while chars.next_if(|c| *c != kind).is_some() {}
+                    if chars.next() != Some(kind) || chars.next() != Some(']') {
+                        bail!("Unterminated [{kind} in bracket expression");
+                    }
+                }
+                _ => bail!("Nested brackets aren't supported by yabai"),
+            },
+            '&' | '-' | '~' if previous == Some(c) => {
+                bail!("Set operations ({c}{c}) aren't supported by yabai")
+            }
+            _ => {}
+        }
+        previous = Some(c);
+    }
+}
+
+/// Bundle id of the app running as `pid`, looked up once per process.
+fn bundle_id(pid: u32) -> Option<String> {
+    if trace::replaying() {
+        return None;
+    }
+    let mut cache = BUNDLE_IDS.lock().unwrap();
+    let cache = cache.get_or_insert_with(HashMap::new);
+    if let Some(id) = cache.get(&pid) {
+        return id.clone();
+    }
+
+    let script = format!(
+        r#"tell application "System Events" to get bundle identifier of first process whose unix id is {pid}"#
+    );
+    let id = Command::new("osascript")
+        .args(["-e", &script])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|id| !id.is_empty() && id != "missing value");
+    if id.is_none() {
+        tracing::debug!("Unable to find the bundle id of process {pid}");
+    }
+    cache.insert(pid, id.clone());
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(app: &str, title: &str) -> Window {
+        Window {
+            app: app.into(),
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn exact_title_with_metacharacters() {
+        let title = "notes (draft) [1] - 50% *done* {x} a|b $5 ^.+?\\";
+        let matcher = Matcher::title_exact(title);
+
+        assert!(matcher.matches(&window("Notes", title)));
+        assert!(!matcher.matches(&window("Notes", "notes (draft)")));
+        // What yabai gets parses back to the same pattern, so both sides agree.
+        let rule = &matcher.rule_args()[0];
+        let pattern = rule
+            .strip_prefix("title=")
+            .unwrap()
+            .parse::<Pattern>()
+            .unwrap();
+        assert_eq!(Some(pattern), matcher.title);
+    }
+
+    #[test]
+    fn patterns_compile_once() {
+        let matcher: Matcher = serde_json::from_str(r#"{"app": "^(Safari|Firefox)$"}"#).unwrap();
+
+        assert!(matcher.matches(&window("Firefox", "")));
+        assert!(!matcher.matches(&window("Firefox Nightly", "")));
+        assert_eq!(matcher.rule_args(), ["app=^(Safari|Firefox)$"]);
+    }
+
+    #[test]
+    fn reject_syntax_yabai_lacks() {
+        for pattern in [
+            r"\d+",
+            r"\bword",
+            r"\p{L}",
+            r"a*?",
+            r"(?i)safari",
+            r"(?=a)",
+            r"[\w]",
+            r"[a-z&&[^m]]",
+            r"[a--b]",
+            r"[abc",
+            "x\\",
+        ] {
+            assert!(pattern.parse::<Pattern>().is_err(), "{pattern}");
+        }
+        for pattern in [
+            r"^a.b$",
+            r"\.app$",
+            r"[]a]",
+            r"[^]a-z]",
+            r"[[:alpha:]]+",
+            r"(a|b)?c{1,2}",
+        ] {
+            assert!(pattern.parse::<Pattern>().is_ok(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn validate_requires_criteria() {
+        assert!(Matcher::default().validate().is_err());
+        let matcher = Matcher {
+            bundle_id: Some("com.apple.Notes".into()),
+            ..Default::default()
+        };
+        assert!(matcher.validate().is_ok());
+        assert!(matcher.rule_args().is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::output;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, UnixListener};
+
+/// Metrics of the process, shared by the runtime and yabai sockets.
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+/// Upper bounds of latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Latency and outcome of a kind of request.
+#[derive(Debug, Default, Clone)]
+struct Timing {
+    count: u64,
+    errors: u64,
+    total: Duration,
+    max: Duration,
+    /// Observations below each of `BUCKETS`, cumulative.
+    buckets: [u64; BUCKETS.len()],
+    /// Queries retried because of empty responses, yabai requests only.
+    retries: u64,
+}
+
+impl Timing {
+    fn observe(&mut self, elapsed: Duration, ok: bool) {
+        self.count += 1;
+        self.errors += u64::from(!ok);
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        for (count, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            *count += u64::from(elapsed.as_secs_f64() <= bound);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Metrics {
+    /// Daemon requests by handler, e.g. `event window_focused` or `window`.
+    handlers: BTreeMap<String, Timing>,
+    /// yabai requests by command, e.g. `query --windows`.
+    yabai: BTreeMap<String, Timing>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+            yabai: BTreeMap::new(),
+        }
+    }
+}
+
+/// Record the time spent handling a daemon request.
+pub fn observe_handler(handler: &str, elapsed: Duration, ok: bool) {
+    let mut metrics = METRICS.lock().unwrap();
+    let timing = metrics.handlers.entry(handler.to_string()).or_default();
+    timing.observe(elapsed, ok);
+}
+
+/// Record the time taken by yabai to answer `command`.
+pub fn observe_yabai(command: &str, elapsed: Duration, ok: bool) {
+    let mut metrics = METRICS.lock().unwrap();
+    let timing = metrics.yabai.entry(command.to_string()).or_default();
+    timing.observe(elapsed, ok);
+}
+
+/// Count a query retried because yabai answered with an empty response.
+pub fn retried_query(command: &str) {
+    let mut metrics = METRICS.lock().unwrap();
+    metrics
+        .yabai
+        .entry(command.to_string())
+        .or_default()
+        .retries += 1;
+}
+
+/// Metrics as a table, one line per handler and yabai command.
+pub fn summary() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let header = ["KIND", "NAME", "COUNT", "ERRORS", "RETRIES", "MEAN", "MAX"];
+    let mut rows = vec![header.map(String::from).to_vec()];
+    let timings = metrics
+        .handlers
+        .iter()
+        .map(|(name, timing)| ("handler", name, timing))
+        .chain(metrics.yabai.iter().map(|(name, t)| ("yabai", name, t)));
+    for (kind, name, timing) in timings {
+        let mean = timing
+            .total
+            .checked_div(timing.count as u32)
+            .unwrap_or_default();
+        rows.push(vec![
+            kind.to_string(),
+            name.clone(),
+            timing.count.to_string(),
+            timing.errors.to_string(),
+            timing.retries.to_string(),
+            format!("{mean:.1?}"),
+            format!("{:.1?}", timing.max),
+        ]);
+    }
+    output::table(&rows)
+}
+
+/// Metrics in the Prometheus text exposition format.
+pub fn prometheus() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut text = String::new();
+    histogram(
+        &mut text,
+        "yctrl_handler_duration_seconds",
+        "Time spent handling daemon requests.",
+        "handler",
+        &metrics.handlers,
+    );
+    counter(
+        &mut text,
+        "yctrl_handler_errors_total",
+        "Daemon requests that failed.",
+        "handler",
+        metrics.handlers.iter().map(|(name, t)| (name, t.errors)),
+    );
+    histogram(
+        &mut text,
+        "yctrl_yabai_request_duration_seconds",
+        "Time taken by yabai to answer requests.",
+        "command",
+        &metrics.yabai,
+    );
+    counter(
+        &mut text,
+        "yctrl_yabai_request_errors_total",
+        "yabai requests that failed or were rejected.",
+        "command",
+        metrics.yabai.iter().map(|(name, t)| (name, t.errors)),
+    );
+    counter(
+        &mut text,
+        "yctrl_yabai_query_retries_total",
+        "yabai queries retried after an empty response.",
+        "command",
+        metrics.yabai.iter().map(|(name, t)| (name, t.retries)),
+    );
+    text
+}
+
+fn histogram(
+    text: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    timings: &BTreeMap<String, Timing>,
+) {
+    writeln!(text, "# HELP {name} {help}\n# TYPE {name} histogram").unwrap();
+    for (value, timing) in timings {
+        let value = escape(value);
+        for (count, bound) in timing.buckets.iter().zip(BUCKETS) {
+            writeln!(
+                text,
+                "{name}_bucket{{{label}=\"{value}\",le=\"{bound}\"}} {count}"
+            )
+            .unwrap();
+        }
+        let (count, sum) = (timing.count, timing.total.as_secs_f64());
+        writeln!(
+            text,
+            "{name}_bucket{{{label}=\"{value}\",le=\"+Inf\"}} {count}"
+        )
+        .unwrap();
+        writeln!(text, "{name}_sum{{{label}=\"{value}\"}} {sum}").unwrap();
+        writeln!(text, "{name}_count{{{label}=\"{value}\"}} {count}").unwrap();
+    }
+}
+
+fn counter<'a>(
+    text: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: impl Iterator<Item = (&'a String, u64)>,
+) {
+    writeln!(text, "# HELP {name} {help}\n# TYPE {name} counter").unwrap();
+    for (value, count) in values {
+        writeln!(text, "{name}{{{label}=\"{}\"}} {count}", escape(value)).unwrap();
+    }
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serve metrics over HTTP in the Prometheus format on `addr`, a TCP port, `host:port`
+/// or the path of a Unix socket.
+pub async fn serve(addr: &str) -> Result<()> {
+    if addr.contains('/') {
+        std::fs::remove_file(addr).ok();
+        let listener = UnixListener::bind(addr)
+            .with_context(|| format!("Unable to serve metrics on {addr}"))?;
+        tracing::info!("Serving metrics on {addr}");
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(respond(stream));
+        }
+    }
+
+    let addr = match addr.parse::<u16>() {
+        Ok(port) => format!("127.0.0.1:{port}"),
+        Err(_) => addr.to_string(),
+    };
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Unable to serve metrics on {addr}"))?;
+    tracing::info!("Serving metrics on http://{addr}/metrics");
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(respond(stream));
+    }
+}
+
+/// Answer any HTTP request with the metrics.
+async fn respond<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) {
+    // The request doesn't matter, its headers are read before responding to keep clients happy.
+    let (mut request, mut buf) = (vec![], [0; 1024]);
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let body = prometheus();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        tracing::debug!("Unable to send metrics: {e}");
+    }
+    stream.shutdown().await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets() {
+        let mut timing = Timing::default();
+        timing.observe(Duration::from_millis(3), true);
+        timing.observe(Duration::from_millis(30), false);
+
+        assert_eq!(timing.buckets[..4], [0, 1, 1, 1]);
+        assert_eq!(timing.buckets[4], 2);
+        assert_eq!((timing.count, timing.errors), (2, 1));
+        assert_eq!(timing.max, Duration::from_millis(30));
+    }
+
+    #[test]
+    fn prometheus_format() {
+        observe_yabai("query --test", Duration::from_millis(2), true);
+        retried_query("query --test");
+
+        let text = prometheus();
+        assert!(text.contains(
+            "yctrl_yabai_request_duration_seconds_bucket{command=\"query --test\",le=\"0.005\"} 1"
+        ));
+        assert!(text.contains("yctrl_yabai_query_retries_total{command=\"query --test\"} 1"));
+    }
+}
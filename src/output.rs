@@ -0,0 +1,322 @@
+use crate::yabai::{Display, Space, Window};
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// Output format of queries: `json`, `table` or jq-style fields (e.g. `.id,.frame.w`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Table,
+    /// Field paths, printed tab separated, one line per object.
+    Fields(Vec<Vec<String>>),
+}
+
+/// Query response deserialized to typed models.
+#[derive(Debug)]
+pub enum Query {
+    Windows(Vec<Window>),
+    Spaces(Vec<Space>),
+    Displays(Vec<Display>),
+    /// Objects limited to the given properties, as returned by yabai when asked for some
+    /// properties only.
+    Properties(Vec<String>, Vec<Value>),
+}
+
+impl Query {
+    /// Parse `raw` response to a query of windows, spaces or displays.
+    ///
+    /// Single objects (e.g. `--windows --window`) are treated as a list of one. When only
+    /// some `properties` were queried, objects are kept as is so that only those get rendered.
+    pub fn parse(kind: &str, raw: &str, properties: Option<Vec<String>>) -> Result<Self> {
+        let value = serde_json::from_str::<Value>(raw).context("Malformed query response")?;
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        if let Some(properties) = properties {
+            return Ok(Self::Properties(properties, values));
+        }
+        Ok(match kind {
+            "windows" => Self::Windows(from_values(values)?),
+            "spaces" => Self::Spaces(from_values(values)?),
+            "displays" => Self::Displays(from_values(values)?),
+            _ => bail!("Unsupported query {kind}"),
+        })
+    }
+
+    /// Objects as serialized by yabai.
+    fn values(&self) -> Result<Vec<Value>> {
+        let values = match self {
+            Self::Windows(windows) => serde_json::to_value(windows)?,
+            Self::Spaces(spaces) => serde_json::to_value(spaces)?,
+            Self::Displays(displays) => serde_json::to_value(displays)?,
+            Self::Properties(_, values) => return Ok(values.clone()),
+        };
+        match values {
+            Value::Array(values) => Ok(values),
+            _ => unreachable!(),
+        }
+    }
+
+    fn table(&self) -> (Vec<String>, Vec<Vec<String>>) {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+        match self {
+            Self::Windows(windows) => (
+                header(&["ID", "PID", "APP", "TITLE", "SPACE", "DISPLAY", "FLAGS"]),
+                windows
+                    .iter()
+                    .map(|w| {
+                        let flags = [
+                            flag(w.has_focus, 'f'),
+                            flag(w.is_floating, 'F'),
+                            flag(w.is_sticky, 's'),
+                            flag(w.is_minimized, 'm'),
+                            flag(w.is_hidden, 'h'),
+                            flag(w.has_fullscreen_zoom, 'z'),
+                        ];
+                        vec![
+                            w.id.to_string(),
+                            w.pid.to_string(),
+                            w.app.clone(),
+                            w.title.clone(),
+                            w.space.to_string(),
+                            w.display.to_string(),
+                            flags.iter().collect(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            Self::Spaces(spaces) => (
+                header(&[
+                    "INDEX", "ID", "LABEL", "TYPE", "DISPLAY", "WINDOWS", "FLAGS",
+                ]),
+                spaces
+                    .iter()
+                    .map(|s| {
+                        let flags = [
+                            flag(s.has_focus, 'f'),
+                            flag(s.is_visible, 'v'),
+                            flag(s.is_native_fullscreen, 'F'),
+                        ];
+                        vec![
+                            s.index.to_string(),
+                            s.id.to_string(),
+                            s.label.clone(),
+                            s.r#type.clone(),
+                            s.display.to_string(),
+                            s.windows.len().to_string(),
+                            flags.iter().collect(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            Self::Displays(displays) => (
+                header(&["INDEX", "ID", "LABEL", "FRAME", "SPACES", "FLAGS"]),
+                displays
+                    .iter()
+                    .map(|d| {
+                        let spaces = d.spaces.iter().map(u32::to_string).collect::<Vec<_>>();
+                        let frame = d.frame;
+                        vec![
+                            d.index.to_string(),
+                            d.id.to_string(),
+                            d.label.clone(),
+                            format!("{}x{}+{}+{}", frame.w, frame.h, frame.x, frame.y),
+                            spaces.join(","),
+                            flag(d.has_focus, 'f').to_string(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            Self::Properties(properties, values) => (
+                properties.iter().map(|p| p.to_uppercase()).collect(),
+                values
+                    .iter()
+                    .map(|value| {
+                        properties
+                            .iter()
+                            .map(|p| field(value, std::slice::from_ref(p)).unwrap_or_default())
+                            .collect()
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Render the query in the given format.
+    pub fn render(&self, format: &Format) -> Result<String> {
+        Ok(match format {
+            Format::Json => serde_json::to_string_pretty(&self.values()?)?,
+            Format::Table => {
+                let (header, rows) = self.table();
+                table(&[vec![header], rows].concat())
+            }
+            Format::Fields(paths) => self
+                .values()?
+                .iter()
+                .map(|value| {
+                    paths
+                        .iter()
+                        .map(|path| field(value, path))
+                        .collect::<Result<Vec<_>>>()
+                        .map(|fields| fields.join("\t"))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join("\n"),
+        })
+    }
+}
+
+fn header(columns: &[&str]) -> Vec<String> {
+    columns.iter().map(|c| c.to_string()).collect()
+}
+
+fn from_values<T: DeserializeOwned>(values: Vec<Value>) -> Result<Vec<T>> {
+    values
+        .into_iter()
+        .map(|v| serde_json::from_value(v).context("Malformed query response"))
+        .collect()
+}
+
+/// Value at `path`, with strings unquoted.
+fn field(value: &Value, path: &[String]) -> Result<String> {
+    let mut value = value;
+    for key in path {
+        value = match value {
+            Value::Object(object) => object.get(key),
+            Value::Array(values) => key.parse::<usize>().ok().and_then(|i| values.get(i)),
+            _ => None,
+        }
+        .with_context(|| format!("No field .{}", path.join(".")))?;
+    }
+    Ok(match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    })
+}
+
+/// Left aligned columns separated by two spaces.
+pub fn table(rows: &[Vec<String>]) -> String {
+    let widths = rows.iter().fold(vec![], |mut widths: Vec<usize>, row| {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+        widths
+    });
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "json" => Self::Json,
+            "table" => Self::Table,
+            s if s.starts_with('.') => Self::Fields(
+                s.split(',')
+                    .map(|path| {
+                        let Some(path) = path.trim().strip_prefix('.') else {
+                            bail!("Invalid field {path}, expected .<field>[.<field>...]");
+                        };
+                        Ok(path
+                            .split('.')
+                            .filter(|key| !key.is_empty() && *key != "[]")
+                            .map(|key| key.trim_start_matches("[]").to_string())
+                            .collect())
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            _ => bail!("Invalid format {s}, expected json, table or fields such as .id,.app"),
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json => f.write_str("json"),
+            Self::Table => f.write_str("table"),
+            Self::Fields(paths) => {
+                let paths = paths.iter().map(|p| format!(".{}", p.join(".")));
+                f.write_str(&paths.collect::<Vec<_>>().join(","))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows() -> Query {
+        let path = format!(
+            "{}/tests/fixtures/yabai-6.0.0/windows.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        Query::parse("windows", &std::fs::read_to_string(path).unwrap(), None).unwrap()
+    }
+
+    fn projected() -> Query {
+        let raw = r#"[{"id": 1, "app": "Alacritty"}, {"id": 2, "app": "Safari"}]"#;
+        Query::parse("windows", raw, Some(vec!["id".into(), "app".into()])).unwrap()
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("table".parse::<Format>().unwrap(), Format::Table);
+        assert_eq!(
+            ".[].id, .frame.w".parse::<Format>().unwrap(),
+            Format::Fields(vec![vec!["id".into()], vec!["frame".into(), "w".into()]])
+        );
+        assert!("yaml".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn render_fields() {
+        let format = ".app,.has-focus".parse().unwrap();
+        let output = windows().render(&format).unwrap();
+        let Query::Windows(windows) = windows() else {
+            unreachable!()
+        };
+        let focused = windows.iter().find(|w| w.has_focus).unwrap();
+        assert!(output
+            .lines()
+            .any(|line| line == format!("{}\ttrue", focused.app)));
+    }
+
+    #[test]
+    fn render_table() {
+        let output = windows().render(&Format::Table).unwrap();
+        let mut lines = output.lines();
+        assert!(lines.next().unwrap().starts_with("ID  "));
+        assert_eq!(lines.count(), windows().values().unwrap().len());
+    }
+
+    #[test]
+    fn render_requested_properties_only() {
+        let output = projected().render(&Format::Table).unwrap();
+        assert_eq!(output, "ID  APP\n1   Alacritty\n2   Safari");
+
+        let output = projected().render(&".app,.id".parse().unwrap()).unwrap();
+        assert_eq!(output, "Alacritty\t1\nSafari\t2");
+        assert!(projected().render(&".title".parse().unwrap()).is_err());
+    }
+}
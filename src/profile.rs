@@ -0,0 +1,97 @@
+use crate::yabai::{Command, Layout, Padding, Socket, Space, SpaceAction, SpaceSelector};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Space settings applied when a space, matched by label, is focused or created.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SpaceProfile {
+    /// Layout of the space.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+    /// Padding between the windows and the edges of the screen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding: Option<Padding>,
+    /// Gap between windows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap: Option<u32>,
+    /// Maximum number of tiled windows in the space.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_windows: Option<usize>,
+}
+
+impl SpaceProfile {
+    /// Apply profile settings that differ from the current state of `space`.
+    ///
+    /// yabai doesn't report padding and gap along with the space, they're compared with the
+    /// profile `applied` to the space before, if any. Returns the new layout of the space, if
+    /// it changed.
+    pub async fn apply(
+        &self,
+        yabai: &Socket,
+        space: &Space,
+        applied: Option<&SpaceProfile>,
+    ) -> Result<Option<Layout>> {
+        for action in self.actions(space, applied) {
+            let command = Command::space_at(SpaceSelector::Index(space.index), action);
+            yabai.run(&command).await?;
+        }
+
+        Ok(self.layout.filter(|l| l.to_string() != space.r#type))
+    }
+
+    fn actions(&self, space: &Space, applied: Option<&SpaceProfile>) -> Vec<SpaceAction> {
+        let mut actions = vec![];
+        let layout = self.layout.filter(|l| l.to_string() != space.r#type);
+        actions.extend(layout.map(SpaceAction::Layout));
+        let padding = self
+            .padding
+            .filter(|p| applied.is_none_or(|a| a.padding != Some(*p)));
+        actions.extend(padding.map(SpaceAction::Padding));
+        let gap = self
+            .gap
+            .filter(|g| applied.is_none_or(|a| a.gap != Some(*g)));
+        actions.extend(gap.map(SpaceAction::Gap));
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_apply_changes() {
+        let profile = SpaceProfile {
+            layout: Some(Layout::Stack),
+            padding: Some(Padding {
+                top: 10,
+                ..Default::default()
+            }),
+            gap: Some(4),
+            max_windows: None,
+        };
+        let space = Space {
+            r#type: "bsp".into(),
+            ..Default::default()
+        };
+
+        assert_eq!(profile.actions(&space, None).len(), 3);
+
+        let space = Space {
+            r#type: "stack".into(),
+            ..Default::default()
+        };
+        assert!(profile.actions(&space, Some(&profile)).is_empty());
+
+        let applied = SpaceProfile {
+            gap: Some(8),
+            ..profile.clone()
+        };
+        assert_eq!(
+            profile.actions(&space, Some(&applied)),
+            [SpaceAction::Gap(4)]
+        );
+    }
+}
@@ -1,22 +1,48 @@
+use crate::cli::DaemonArgs;
+use crate::hook;
+use crate::logging;
+use crate::metrics;
 use crate::scratchpad::ScratchpadEvent;
 use crate::state::{SharedState, State};
-use crate::yabai::{ApplicationEvent, Event};
-use anyhow::{bail, Context, Ok, Result};
+use crate::subscription::{Update, TOPICS};
+use crate::trace::{self, Entry};
+use crate::yabai::{self, ApplicationEvent, Event, Layout, Socket, Space};
+use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
 use std::fs;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::Mutex;
 use tracing::Level;
 
+mod focus;
+mod monocle;
 mod space_event;
+mod swallow;
 mod window_event;
 
 const SOCKET_PATH: &str = "/tmp/yctrl.socket";
 
+/// yabai events handled by the daemon, which should be forwarded through signals.
+const HANDLED_EVENTS: &[&str] = &[
+    "window_created",
+    "window_destroyed",
+    "window_focused",
+    "window_minimized",
+    "space_changed",
+    "space_created",
+    "space_destroyed",
+    "application_hidden",
+];
+
+/// Leading byte of a response signaling that the request failed (same as yabai).
+const FAILURE_MESSAGE: u8 = 0x07;
+
 #[async_trait]
 pub trait EventHandler {
     async fn handle(&self, state: SharedState) -> Result<()>;
@@ -39,16 +65,41 @@ impl EventHandler for Event {
     }
 }
 
-pub async fn start() -> Result<()> {
+pub async fn start(args: DaemonArgs) -> Result<()> {
     let state = Arc::new(Mutex::new(State::default()));
 
-    configure_tracing_subscriber()?;
+    state.lock().await.log_filter = Some(logging::configure(&args.log)?);
+
+    if let Err(e) = state.lock().await.load_config_file().await {
+        tracing::error!("{:?}", e);
+    }
+
+    match check_signals().await {
+        Err(e) if matches!(e.downcast_ref(), Some(yabai::Error::NotRunning { .. })) => {
+            tracing::warn!("{e}, skipping signals check")
+        }
+        Err(e) => tracing::warn!("Unable to check yabai signals: {:?}", e),
+        Ok(()) => (),
+    }
+
+    // Started once set up, so that replays only cover requests.
+    if let Some(path) = args.trace {
+        trace::start(&path, &state.lock().await.config)?;
+    }
 
     if fs::metadata(SOCKET_PATH).is_ok() {
         fs::remove_file(SOCKET_PATH)
             .with_context(|| format!("could not delete previous socket at {:?}", SOCKET_PATH))?;
     }
 
+    if let Some(addr) = args.metrics_listen {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(&addr).await {
+                tracing::error!("{:?}", e);
+            }
+        });
+    }
+
     let listener = UnixListener::bind(SOCKET_PATH)?;
     tracing::info!("Listening on {SOCKET_PATH}");
     loop {
@@ -62,30 +113,123 @@ pub async fn start() -> Result<()> {
     }
 }
 
+/// Warn about handled events yabai isn't configured to forward.
+async fn check_signals() -> Result<()> {
+    let signals = Socket::new()?.signals().await?;
+    for event in HANDLED_EVENTS {
+        let forwarded = signals
+            .iter()
+            .any(|s| s.event == *event && s.action.contains("yctrl"));
+        if !forwarded {
+            tracing::warn!("No yabai signal forwards {event} to yctrl");
+        }
+    }
+    Ok(())
+}
+
 async fn handle(mut s: UnixStream, state: SharedState) -> Result<()> {
-    let mut rng = StdRng::from_entropy();
-    let id: u32 = rng.gen_range(222..999);
+    let mut request = String::default();
 
-    let mut response = String::default();
+    s.read_to_string(&mut request).await?;
+
+    // Subscriptions keep the connection open rather than sending a single response.
+    let mut words = request.split_whitespace();
+    if words.next() == Some("subscribe") {
+        return stream_updates(s, words.map(String::from).collect(), state).await;
+    }
+
+    let started = Instant::now();
+    let result = process(&request, state).await;
+    metrics::observe_handler(&handler_name(&request), started.elapsed(), result.is_ok());
+    let response = match &result {
+        Ok(response) => response.as_bytes().to_vec(),
+        Err(e) => [&[FAILURE_MESSAGE], format!("{e:#}").as_bytes()].concat(),
+    };
+
+    // Clients sending events may not wait for a response.
+    s.write_all(&response).await.ok();
+    s.shutdown().await.ok();
 
-    s.read_to_string(&mut response).await?;
+    result.map(|_| ())
+}
 
-    let mut args: Vec<&str> = response.trim().split_whitespace().collect();
+/// Name of the handler of `request` used to group metrics, e.g. `event window_focused`.
+fn handler_name(request: &str) -> String {
+    let mut words = request.split_whitespace();
+    match words.next() {
+        Some("event") => format!("event {}", words.next().unwrap_or_default()),
+        word => word.unwrap_or_default().to_string(),
+    }
+}
+
+/// Stream updates matching `topics` to the client until it disconnects.
+async fn stream_updates(mut s: UnixStream, topics: Vec<String>, state: SharedState) -> Result<()> {
+    if let Some(topic) = topics.iter().find(|t| !TOPICS.contains(&t.as_str())) {
+        let message = format!(
+            "Unknown topic {topic}, expected one of {}",
+            TOPICS.join(", ")
+        );
+        s.write_all(&[&[FAILURE_MESSAGE], message.as_bytes()].concat())
+            .await
+            .ok();
+        bail!(message);
+    }
+
+    let mut updates = state.lock().await.subscribers.subscribe();
+    tracing::debug!("Client subscribed to {topics:?}");
+    loop {
+        let update = match updates.recv().await {
+            Ok(update) => update,
+            Err(RecvError::Lagged(missed)) => {
+                tracing::warn!("Subscriber is too slow, skipped {missed} updates");
+                continue;
+            }
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        if !update.matches(&topics) {
+            continue;
+        }
+
+        let line = serde_json::to_string(&update)? + "\n";
+        if s.write_all(line.as_bytes()).await.is_err() {
+            tracing::debug!("Subscriber disconnected");
+            return Ok(());
+        }
+    }
+}
+
+/// Process a request and return the response to send back to the client.
+pub async fn process(request: &str, state: SharedState) -> Result<String> {
+    trace::record(Entry::Request {
+        request: request.to_string(),
+    });
+
+    let mut rng = StdRng::from_entropy();
+    let id: u32 = rng.gen_range(222..999);
+
+    let mut args: Vec<&str> = request.split_whitespace().collect();
+    if args.is_empty() {
+        bail!("Empty request");
+    }
 
     // Get Request type
     let rtype: &str = args.remove(0);
 
     let span = tracing::trace_span!("Request", "[{}]", id);
+    let mut response = String::default();
 
     match rtype {
         "event" => {
             let event = Event::try_from(args)?;
             tracing::event!(parent: &span, Level::DEBUG, "{}", event);
-            event.handle(state).await?;
+            let result = event.handle(state.clone()).await;
+            state.lock().await.subscribers.publish(Update::from(&event));
+            hook::trigger(&state, &event).await?;
+            result?;
         }
         "config" => {
-            tracing::event!(parent: &span, Level::INFO, "Updating configuration");
-            state.lock().await.handle(args).await?;
+            tracing::event!(parent: &span, Level::INFO, "Handling config request");
+            response = state.lock().await.handle(args).await?;
 
             tracing::event!(
                 parent: &span,
@@ -95,69 +239,157 @@ async fn handle(mut s: UnixStream, state: SharedState) -> Result<()> {
             );
         }
         "scratchpad" => {
+            if args.is_empty() {
+                bail!("Missing scratchpad tag");
+            }
             let tag = args.remove(0);
             tracing::event!(parent: &span, Level::INFO, "Toggling scratchpad: {tag}");
             ScratchpadEvent::toggle(state, tag).await?;
         }
+        "window" => {
+            let yabai = Socket::new()?;
+            let state = &mut *state.lock().await;
+            let history = &mut state.focus_history;
+            match args[..] {
+                ["focus", "back"] => history.focus_back(&yabai).await?,
+                ["focus", "mru", "next"] => history.focus_mru(&yabai, true).await?,
+                ["focus", "mru", "prev"] => history.focus_mru(&yabai, false).await?,
+                ["mru", n] => {
+                    let n = n
+                        .parse::<usize>()
+                        .with_context(|| format!("Invalid count: {n}"))?;
+                    let windows = yabai.windows("all").await?;
+                    let Some(id) = history.sort(&windows).get(n).copied() else {
+                        bail!("No window {n} steps back in history");
+                    };
+                    response = id.to_string();
+                }
+                ["monocle"] => {
+                    monocle::toggle(&yabai, state).await?;
+                }
+                _ => bail!("Unsupported window request: {args:?}"),
+            }
+        }
+        "daemon" => match args[..] {
+            ["log-level", directives] => {
+                let Some(handle) = &state.lock().await.log_filter else {
+                    bail!("Logging isn't configured");
+                };
+                handle.reload(logging::filter(directives)?)?;
+                tracing::info!("Log filter changed to {directives}");
+            }
+            ["metrics"] => response = metrics::summary(),
+            ["metrics", "prometheus"] => response = metrics::prometheus(),
+            _ => bail!("Unsupported daemon request: {args:?}"),
+        },
+        "space" => match args[..] {
+            ["layout", space, layout] => {
+                let space = space
+                    .parse::<u32>()
+                    .with_context(|| format!("Invalid space index: {space}"))?;
+                let layout = layout.parse::<Layout>()?;
+                let update = Update::Layout { space, layout };
+                state.lock().await.subscribers.publish(update);
+            }
+            ["profile", name] => {
+                tracing::event!(parent: &span, Level::INFO, "Switching space profile: {name}");
+                state.lock().await.switch_space_profile(name).await?;
+            }
+            ["focus", "back"] => {
+                let yabai = Socket::new()?;
+                state
+                    .lock()
+                    .await
+                    .space_history
+                    .focus_back(&yabai, 1)
+                    .await?;
+            }
+            ["focus", "back", n] => {
+                let n = n.parse().with_context(|| format!("Invalid count: {n}"))?;
+                let yabai = Socket::new()?;
+                state
+                    .lock()
+                    .await
+                    .space_history
+                    .focus_back(&yabai, n)
+                    .await?;
+            }
+            _ => bail!("Unsupported space request: {args:?}"),
+        },
         _ => {
             bail!("Request type: '{rtype}' is not supported.")
         }
     }
 
+    Ok(response)
+}
+
+/// Execute argument in the runtime
+pub async fn execute<S: AsRef<str>>(args: &[S]) -> Result<()> {
+    request(args).await.map(|_| ())
+}
+
+/// Send request to the runtime and return its response.
+pub async fn request<S: AsRef<str>>(args: &[S]) -> Result<String> {
+    let mut stream = send(args).await?;
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await?;
+    if buf.first() == Some(&FAILURE_MESSAGE) {
+        bail!("yctrl: {}", String::from_utf8_lossy(&buf[1..]).trim());
+    }
+
+    String::from_utf8(buf).map_err(anyhow::Error::new)
+}
+
+/// Let the runtime, if running, publish the layout change made by the yabai command `args`
+/// (e.g. `space 2 --layout bsp`), which it otherwise never hears of.
+pub async fn notify_layout(yabai: &Socket, args: &[String]) -> Result<()> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let (selector, layout) = match args[..] {
+        ["space", "--layout", layout] => (None, layout),
+        ["space", selector, "--layout", layout] => (Some(selector), layout),
+        _ => return Ok(()),
+    };
+    let Ok(layout) = layout.parse::<Layout>() else {
+        return Ok(());
+    };
+    let space = match selector {
+        Some(selector) => {
+            yabai
+                .query::<Space, _>(&["query", "--spaces", "--space", selector])
+                .await?
+        }
+        None => yabai.focused_space().await?,
+    };
+
+    let (space, layout) = (space.index.to_string(), layout.to_string());
+    if let Err(e) = execute(&["space", "layout", &space, &layout]).await {
+        tracing::debug!("Layout change not published: {e:#}");
+    }
     Ok(())
 }
 
-fn configure_tracing_subscriber() -> Result<()> {
-    use tracing::metadata::LevelFilter;
-    use tracing::subscriber::set_global_default;
-    use tracing_appender::rolling;
-    use tracing_subscriber::fmt::format::FmtSpan;
-    use tracing_subscriber::fmt::writer::MakeWriterExt;
-    use tracing_subscriber::fmt::Layer;
-    use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
-
-    use tracing_subscriber::{registry, EnvFilter};
-    let fmt_file = Layer::new()
-        .with_writer(rolling::never("/tmp", "yctrl.log").with_max_level(Level::INFO))
-        .with_target(false)
-        .with_thread_names(false)
-        .without_time()
-        .with_span_events(FmtSpan::CLOSE)
-        .with_thread_ids(false);
-
-    let fmt_stdout = Layer::new()
-        .with_writer(std::io::stdout)
-        .with_span_events(FmtSpan::CLOSE)
-        .with_target(false)
-        .with_line_number(true)
-        .without_time();
-
-    set_global_default(
-        registry()
-            .with(
-                EnvFilter::try_from_default_env()
-                    .map(|d| {
-                        d.add_directive("tokio::net=trace".parse().unwrap())
-                            .add_directive("yctrl=trace".parse().unwrap())
-                    })
-                    .unwrap_or_else(|_| {
-                        EnvFilter::builder()
-                            .with_default_directive(LevelFilter::INFO.into())
-                            .parse("")
-                            .unwrap()
-                    }),
-            )
-            .with(fmt_file)
-            .with(fmt_stdout),
-    )?;
+/// Print updates of the given topics (all if empty) as JSON lines, until the runtime stops.
+pub async fn subscribe(topics: &[String]) -> Result<()> {
+    let args = [&["subscribe".to_string()], topics].concat();
+    let mut lines = BufReader::new(send(&args).await?).lines();
+    while let Some(line) = lines.next_line().await? {
+        if let Some(message) = line.strip_prefix(FAILURE_MESSAGE as char) {
+            bail!("yctrl: {}", message.trim());
+        }
+        println!("{line}");
+    }
     Ok(())
 }
 
-/// Execute argument in the runtime
-pub async fn execute(args: &Vec<String>) -> Result<()> {
-    let mut stream = UnixStream::connect(SOCKET_PATH).await?;
+/// Connect to the runtime and send a request.
+async fn send<S: AsRef<str>>(args: &[S]) -> Result<UnixStream> {
+    let mut stream = UnixStream::connect(SOCKET_PATH)
+        .await
+        .with_context(|| format!("yctrl: Unable to connect to {SOCKET_PATH}, is it running?"))?;
     stream.writable().await?;
-    stream.write_all(args.join(" ").as_str().as_ref()).await?;
-    stream.flush().await?;
-    Ok(())
+    let request = args.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(" ");
+    stream.write_all(request.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(stream)
 }
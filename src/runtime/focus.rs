@@ -0,0 +1,46 @@
+use crate::config::FocusRestore;
+use crate::state::State;
+use crate::yabai::{Socket, Window};
+use anyhow::Result;
+
+/// Restore focus to a window in current space, ignoring the `excluded` window.
+///
+/// Depending on configuration, either the window under the mouse or the most recently used
+/// window in current space wins.
+pub async fn restore(yabai: &Socket, state: &mut State, excluded: Option<u32>) -> Result<()> {
+    let mode = *state.config.focus_restore();
+    if mode == FocusRestore::Off {
+        return Ok(());
+    }
+
+    let windows = yabai
+        .windows("current")
+        .await?
+        .into_iter()
+        .filter(|w| Some(w.id) != excluded)
+        .collect::<Vec<Window>>();
+
+    if windows.is_empty() {
+        tracing::debug!("No windows in current space to focus");
+        return Ok(());
+    }
+
+    let space = yabai.focused_space().await?;
+    let mut candidates = state.focus_history.sort_in(space.id, &windows);
+    if mode == FocusRestore::Mouse {
+        if let Some(id) = yabai
+            .mouse_window()
+            .await
+            .map(|w| w.id)
+            .filter(|id| candidates.contains(id))
+        {
+            tracing::debug!("Mouse is over {id}");
+            candidates.retain(|c| *c != id);
+            candidates.insert(0, id);
+        }
+    }
+
+    let id = state.focus_history.focus_first(yabai, candidates).await?;
+    tracing::debug!("Switched focus to {id}");
+    Ok(())
+}
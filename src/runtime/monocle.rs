@@ -0,0 +1,151 @@
+use crate::state::{Monocle, State};
+use crate::yabai::{Command, Socket, Window, WindowAction, WindowSelector, Zoom};
+use anyhow::{Context, Result};
+
+/// Toggle monocle mode of the focused space.
+///
+/// Toggling off restores the zoom windows had before, including the ones that moved to
+/// another space meanwhile.
+pub async fn toggle(yabai: &Socket, state: &mut State) -> Result<()> {
+    let snapshot = yabai.snapshot().await?;
+    let space = snapshot.focused_space().context("No focused space")?;
+
+    if let Some(monocle) = state.monocles.remove(&space.id) {
+        tracing::info!("Disabling monocle in space {}", space.index);
+        for (id, action) in restorations(&monocle, snapshot.windows()) {
+            yabai
+                .run(&Command::window_at(WindowSelector::Id(id), action))
+                .await?;
+        }
+        return Ok(());
+    }
+
+    tracing::info!("Enabling monocle in space {}", space.index);
+    // Floating windows can't be zoomed.
+    let windows = snapshot
+        .windows_in(space)
+        .filter(|w| w.is_shown() && !w.is_floating)
+        .collect::<Vec<_>>();
+    let zoom = windows.iter().map(|w| (w.id, w.zoom())).collect();
+    state.monocles.insert(space.id, Monocle { zoom });
+    if let Some(window) = windows.iter().find(|w| w.has_focus) {
+        zoom_fullscreen(yabai, window).await?;
+    }
+    Ok(())
+}
+
+/// Zoom the focused window if its space is in monocle mode.
+pub async fn focused(yabai: &Socket, window_id: &u32, state: &mut State) -> Result<()> {
+    if state.monocles.is_empty() {
+        return Ok(());
+    }
+
+    let window = yabai.window(window_id).await?;
+    if window.is_floating {
+        return Ok(());
+    }
+    let spaces = yabai.spaces("all").await?;
+    let Some(monocle) = spaces
+        .iter()
+        .find(|s| s.index == window.space)
+        .and_then(|s| state.monocles.get_mut(&s.id))
+    else {
+        return Ok(());
+    };
+
+    // Windows showing up after monocle got enabled are recorded when first focused.
+    monocle.zoom.entry(window.id).or_insert(window.zoom());
+    zoom_fullscreen(yabai, &window).await
+}
+
+async fn zoom_fullscreen(yabai: &Socket, window: &Window) -> Result<()> {
+    if window.zoom() == Zoom::Fullscreen {
+        return Ok(());
+    }
+    let selector = WindowSelector::Id(window.id);
+    yabai
+        .run(&Command::window_at(
+            selector,
+            WindowAction::ToggleZoomFullscreen,
+        ))
+        .await
+}
+
+/// Toggles bringing recorded windows back to their zoom before monocle mode.
+fn restorations(monocle: &Monocle, windows: &[Window]) -> Vec<(u32, WindowAction)> {
+    windows
+        .iter()
+        .filter_map(|window| {
+            let zoom = *monocle.zoom.get(&window.id)?;
+            // Zooms are exclusive, toggling one replaces the other.
+            let action = match (window.zoom(), zoom) {
+                (current, zoom) if current == zoom => return None,
+                (_, Zoom::Parent) | (Zoom::Parent, Zoom::None) => WindowAction::ToggleZoomParent,
+                (_, Zoom::Fullscreen) | (Zoom::Fullscreen, Zoom::None) => {
+                    WindowAction::ToggleZoomFullscreen
+                }
+                (Zoom::None, Zoom::None) => return None,
+            };
+            Some((window.id, action))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32, zoom: Zoom) -> Window {
+        Window {
+            id,
+            has_parent_zoom: zoom == Zoom::Parent,
+            has_fullscreen_zoom: zoom == Zoom::Fullscreen,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn restore_previous_zooms() {
+        let monocle = Monocle {
+            zoom: [
+                (1, Zoom::None),
+                (2, Zoom::Parent),
+                (3, Zoom::Fullscreen),
+                (4, Zoom::None),
+            ]
+            .into(),
+        };
+        let windows = [
+            window(1, Zoom::Fullscreen),
+            window(2, Zoom::Fullscreen),
+            window(3, Zoom::Fullscreen),
+            window(4, Zoom::None),
+            // Never recorded, e.g. created but not focused while in monocle mode.
+            window(5, Zoom::Fullscreen),
+        ];
+
+        assert_eq!(
+            restorations(&monocle, &windows),
+            [
+                (1, WindowAction::ToggleZoomFullscreen),
+                (2, WindowAction::ToggleZoomParent),
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_parent_zoom_lost_to_user() {
+        let monocle = Monocle {
+            zoom: [(1, Zoom::Parent), (2, Zoom::Fullscreen)].into(),
+        };
+        let windows = [window(1, Zoom::None), window(2, Zoom::Parent)];
+
+        assert_eq!(
+            restorations(&monocle, &windows),
+            [
+                (1, WindowAction::ToggleZoomParent),
+                (2, WindowAction::ToggleZoomFullscreen),
+            ]
+        );
+    }
+}
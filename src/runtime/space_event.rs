@@ -1,7 +1,9 @@
-use crate::runtime::EventHandler;
-use crate::state::SharedState;
-use crate::yabai::{self, Socket, Space, SpaceEvent, Window};
-use anyhow::{bail, Result};
+use crate::label;
+use crate::runtime::{focus, EventHandler};
+use crate::state::{SharedState, State};
+use crate::subscription::Update;
+use crate::yabai::{self, Command, Socket, SpaceAction, SpaceEvent, SpaceSelector};
+use anyhow::Result;
 use async_trait::async_trait;
 use std::time::Duration;
 use tokio::time::sleep;
@@ -10,86 +12,88 @@ use tokio::time::sleep;
 impl EventHandler for SpaceEvent {
     async fn handle(&self, state: SharedState) -> Result<()> {
         let yabai = yabai::Socket::new()?;
-        let config = &state.lock().await.config;
+        let mut state = state.lock().await;
 
         match self {
             SpaceEvent::Changed {
                 space_id,
                 recent_space_id,
             } => {
-                // TODO: Make it configurable
-                auto_focus_window(&yabai, space_id).await?;
-                if *config.auto_close_empty_spaces() {
-                    destory_recent_space_when_empty(&yabai, recent_space_id).await?;
+                state.space_history.changed(*space_id, *recent_space_id);
+                apply_space_profile(&yabai, &mut state, space_id).await?;
+                auto_focus_window(&yabai, &mut state).await?;
+                if *state.config.auto_close_empty_spaces()
+                    && destory_recent_space_when_empty(&yabai, recent_space_id).await?
+                {
+                    state.space_history.remove(*recent_space_id);
+                    state.focus_history.remove_space(*recent_space_id);
+                    state.applied_profiles.remove(recent_space_id);
+                    label::apply(&yabai, state.config.space_labels()).await?;
                 }
                 Ok(())
             }
+            SpaceEvent::Created { space_id } => {
+                label::apply(&yabai, state.config.space_labels()).await?;
+                apply_space_profile(&yabai, &mut state, space_id).await
+            }
+            SpaceEvent::Destroyed { space_id } => {
+                state.space_profiles.remove(space_id);
+                state.applied_profiles.remove(space_id);
+                state.monocles.remove(space_id);
+                state.space_history.remove(*space_id);
+                state.focus_history.remove_space(*space_id);
+                label::apply(&yabai, state.config.space_labels()).await
+            }
         }
     }
 }
 
-/// Switch focus to current space open window if focus is in another space window
-async fn auto_focus_window(yabai: &Socket, space_id: &u32) -> Result<()> {
-    // TODO: Should only work if there is no focused window in current space.
-    let windows = yabai
-        .windows("current")
-        .await?
-        .into_iter()
-        .filter(|w| !w.is_minimized && !w.is_hidden)
-        .collect::<Vec<Window>>();
+/// Apply the profile of the space with the given id, if any.
+async fn apply_space_profile(yabai: &Socket, state: &mut State, space_id: &u32) -> Result<()> {
+    let spaces = yabai.spaces("all").await?;
+    let Some(space) = spaces.iter().find(|s| &s.id == space_id) else {
+        return Ok(());
+    };
 
-    if windows.len() == 0 {
-        bail!("No More windows in current space with {space_id}");
+    if let Some(profile) = state.space_profile(space).cloned() {
+        tracing::debug!("Applying profile of space {}", space.index);
+        let applied = state.applied_profiles.get(&space.id);
+        if let Some(layout) = profile.apply(yabai, space, applied).await? {
+            let space = space.index;
+            state.subscribers.publish(Update::Layout { space, layout });
+        }
+        state.applied_profiles.insert(space.id, profile);
     }
 
-    if !windows.iter().any(|w| w.has_focus) {
-        tracing::debug!("Focus is stolen in some other space, fixing ...")
-    } else {
+    Ok(())
+}
+
+/// Switch focus to current space window if focus is in another space window.
+async fn auto_focus_window(yabai: &Socket, state: &mut State) -> Result<()> {
+    let windows = yabai.windows("current").await?;
+    if windows.iter().any(|w| w.has_focus) {
         return Ok(());
     }
 
-    if yabai
-        .execute(&["window", "--focus", "mouse"])
-        .await
-        .is_err()
-    {
-        let focus_window = windows.first().unwrap();
-        tracing::trace!("Switching focus to {}", focus_window.title);
-
-        let focus_window_id = focus_window.id.to_string();
-        let focus_args = &["window", "--focus", &focus_window_id];
-        let focus_result = yabai.execute(focus_args).await;
-
-        if let Err(e) = focus_result {
-            tracing::error!(
-                "Unable to change focus to {}. Cause: {e}",
-                focus_window.title
-            )
-        }
-    };
-    Ok(())
+    tracing::debug!("Focus is stolen in some other space, fixing ...");
+    focus::restore(yabai, state, None).await
 }
 
-/// When recent space is empty, destory it.
+/// When recent space is empty, destory it and return whether it got destroyed.
 /// Credit: @PickingUpPieces
-async fn destory_recent_space_when_empty(yabai: &Socket, recent_space_id: &u32) -> Result<()> {
+async fn destory_recent_space_when_empty(yabai: &Socket, recent_space_id: &u32) -> Result<bool> {
     // Allow some time for yabai to process
     sleep(Duration::new(0, 6)).await;
 
-    // Get most recent space object.
-    let rspace = yabai
-        .spaces("current")
-        .await?
-        .into_iter()
-        .filter(|s| &s.id == recent_space_id)
-        .collect::<Vec<Space>>()
-        .remove(0);
+    let snapshot = yabai.snapshot().await?;
 
-    // Get Most recent space index as a string
-    let rspace_idx = rspace.index.to_string();
+    // Get most recent space object.
+    let Some(rspace) = snapshot.space(*recent_space_id) else {
+        return Ok(false);
+    };
 
-    // Get recent space valid windows.
-    let rspace_windows = yabai.windows(&rspace_idx).await?;
+    // Get recent space windows.
+    let rspace_windows = snapshot.windows_in(rspace).collect::<Vec<_>>();
 
     // Get minimized window count
     let rspace_hidden_windows_count = rspace_windows
@@ -101,9 +105,11 @@ async fn destory_recent_space_when_empty(yabai: &Socket, recent_space_id: &u32)
     let should_keep_rspace = rspace.is_visible || rspace.has_focus || rspace.is_native_fullscreen;
 
     if !should_keep_rspace && rspace_windows.len() == rspace_hidden_windows_count {
-        tracing::info!("Destorying space at index {} ..", rspace_idx);
-        yabai.execute(&["space", &rspace_idx, "--destroy"]).await?;
+        tracing::info!("Destorying space at index {} ..", rspace.index);
+        let destroy = Command::space_at(SpaceSelector::Index(rspace.index), SpaceAction::Destroy);
+        yabai.run(&destroy).await?;
+        return Ok(true);
     }
 
-    Ok(())
+    Ok(false)
 }
@@ -0,0 +1,147 @@
+use crate::history::FocusHistory;
+use crate::state::{SharedState, State};
+use crate::util::ancestors;
+use crate::yabai::{Command, Socket, SpaceSelector, Window, WindowAction, WindowSelector};
+use anyhow::Result;
+
+/// Replace the terminal window `window` was launched from, if any, with `window`.
+///
+/// The terminal window takes part in the tiling again once `window` is destroyed, see
+/// [`release`]. Returns whether the terminal got swallowed.
+pub async fn swallow(yabai: &Socket, window: &Window, state: &SharedState) -> Result<bool> {
+    let terminals = state.lock().await.config.swallow_terminals().clone();
+    if terminals.is_empty() || terminals.contains(&window.app) {
+        return Ok(false);
+    }
+
+    let candidates = yabai
+        .windows("all")
+        .await?
+        .into_iter()
+        .filter(|w| terminals.contains(&w.app) && !w.is_minimized)
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Ok(false);
+    }
+
+    // Listing processes is slow, don't hold the state meanwhile.
+    let ancestors = ancestors(window.pid).await?;
+    let state = &mut *state.lock().await;
+    let Some(terminal) = pick_terminal(&candidates, &ancestors, &state.focus_history) else {
+        return Ok(false);
+    };
+
+    tracing::info!(
+        "{} swallows {} window {}",
+        window.app,
+        terminal.app,
+        terminal.id
+    );
+    let selector = WindowSelector::Id(window.id);
+    let terminal_selector = WindowSelector::Id(terminal.id);
+    if !window.is_floating && !terminal.is_floating {
+        if window.space != terminal.space {
+            let action = WindowAction::Space(SpaceSelector::Index(terminal.space));
+            yabai.run(&Command::window_at(selector, action)).await?;
+        }
+        let action = WindowAction::Swap(terminal_selector);
+        yabai.run(&Command::window_at(selector, action)).await?;
+    }
+    // Recorded before minimizing, so that the resulting event doesn't move focus away.
+    state.swallowed.insert(window.id, terminal.id);
+    let minimize = Command::window_at(terminal_selector, WindowAction::Minimize);
+    if let Err(e) = yabai.run(&minimize).await {
+        state.swallowed.remove(&window.id);
+        return Err(e);
+    }
+
+    Ok(true)
+}
+
+/// Terminal window a window was launched from: among `candidates` owned by the closest of
+/// the window process `ancestors`, the most recently focused one.
+///
+/// Terminals often run every window in one process, the one last focused launched it.
+fn pick_terminal<'a>(
+    candidates: &'a [Window],
+    ancestors: &[u32],
+    history: &FocusHistory,
+) -> Option<&'a Window> {
+    let pid = ancestors
+        .iter()
+        .find(|pid| candidates.iter().any(|w| w.pid == **pid))?;
+    let windows = candidates
+        .iter()
+        .filter(|w| w.pid == *pid)
+        .cloned()
+        .collect::<Vec<_>>();
+    let id = *history.sort(&windows).first()?;
+    candidates.iter().find(|w| w.id == id)
+}
+
+/// Whether the window with the given id is a terminal swallowed by another window.
+pub fn is_swallowed(state: &State, window_id: &u32) -> bool {
+    state.swallowed.values().any(|id| id == window_id)
+}
+
+/// Restore and focus the terminal swallowed by the destroyed window, if any.
+///
+/// yabai tiles the terminal back where it inserts new windows, which isn't necessarily the
+/// slot the destroyed window left. Returns whether a terminal got restored.
+pub async fn release(yabai: &Socket, window_id: &u32, state: &mut State) -> Result<bool> {
+    let Some(terminal) = state.swallowed.remove(window_id) else {
+        return Ok(false);
+    };
+
+    tracing::info!("Restoring terminal window {terminal}");
+    let selector = WindowSelector::Id(terminal);
+    let restored = yabai
+        .run(&Command::window_at(selector, WindowAction::Deminimize))
+        .await;
+    if let Err(e) = restored {
+        // The terminal window may have been closed in the meantime.
+        tracing::debug!("Unable to restore terminal window {terminal}: {e}");
+        return Ok(false);
+    }
+    yabai
+        .run(&Command::window(WindowAction::Focus(selector)))
+        .await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32, pid: u32) -> Window {
+        Window {
+            id,
+            pid,
+            app: "Alacritty".into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pick_most_recent_window_of_closest_ancestor() {
+        let candidates = [window(1, 10), window(2, 20), window(3, 20)];
+        let mut history = FocusHistory::default();
+        history.focused(2, 1);
+        history.focused(3, 1);
+        history.focused(1, 2);
+
+        // The shell (30) runs in terminal 20, itself started from terminal 10.
+        let terminal = pick_terminal(&candidates, &[30, 20, 10], &history);
+        assert_eq!(terminal.map(|w| w.id), Some(3));
+    }
+
+    #[test]
+    fn pick_nothing_without_terminal_ancestor() {
+        let candidates = [window(1, 10)];
+        let history = FocusHistory::default();
+
+        assert!(pick_terminal(&candidates, &[30, 40], &history).is_none());
+        assert!(pick_terminal(&candidates, &[], &history).is_none());
+    }
+}
@@ -1,9 +1,13 @@
 use crate::config::Config;
-use crate::runtime::EventHandler;
-use crate::state::SharedState;
+use crate::constants::SPACE_CREATE_TIMEOUT;
+use crate::runtime::{focus, monocle, swallow, EventHandler};
+use crate::state::{SharedState, State};
 use crate::util::window_hide_current;
-use crate::yabai::{Socket, WindowEvent};
-use anyhow::Result;
+use crate::yabai::{
+    Command, Socket, Space, SpaceAction, SpaceSelector, Window, WindowAction, WindowEvent,
+    WindowSelector,
+};
+use anyhow::{Context, Result};
 use async_trait::async_trait;
 
 #[async_trait]
@@ -11,12 +15,31 @@ impl EventHandler for WindowEvent {
     async fn handle(&self, state: SharedState) -> Result<()> {
         let yabai = Socket::new()?;
         match self {
-            WindowEvent::Created { window_id } => created(&yabai, window_id).await,
-            WindowEvent::Destroyed { window_id } | WindowEvent::Minimized { window_id } => {
-                focus_last(&yabai, window_id).await
+            WindowEvent::Created { window_id } => {
+                let window = yabai.window(window_id).await?;
+                if swallow::swallow(&yabai, &window, &state).await? {
+                    return Ok(());
+                }
+                created(&yabai, &window, &mut *state.lock().await).await
+            }
+            WindowEvent::Destroyed { window_id } => {
+                let state = &mut *state.lock().await;
+                if swallow::release(&yabai, window_id, state).await? {
+                    state.focus_history.remove(*window_id);
+                    return Ok(());
+                }
+                focus_last(&yabai, window_id, state).await
+            }
+            WindowEvent::Minimized { window_id } => {
+                let state = &mut *state.lock().await;
+                // Swallowed terminals are minimized on purpose, focus stays on the new window.
+                if swallow::is_swallowed(state, window_id) {
+                    return Ok(());
+                }
+                focus_last(&yabai, window_id, state).await
             }
             WindowEvent::Focused { window_id } => {
-                focused(&yabai, window_id, &state.lock().await.config).await
+                focused(&yabai, window_id, &mut *state.lock().await).await
             }
             WindowEvent::Moved { window_id } => moved(&yabai, window_id).await,
             WindowEvent::Resized { window_id } => resized(&yabai, window_id).await,
@@ -29,23 +52,22 @@ async fn deminimzed(_yabai: &Socket, _window_id: &u32) -> Result<()> {
     Ok(())
 }
 
-async fn focus_last(yabai: &Socket, window_id: &u32) -> Result<()> {
-    let current_window = yabai.window_by_id("current", window_id).await?;
-    let last_window = yabai.last_window().await?;
-    let last_window_id = last_window.id;
-    tracing::debug!("{last_window:?}");
-    tracing::debug!("{current_window:?}");
+/// Restore focus to a window in current space when the window closed or minimized was
+/// focused and current space lost focus with it.
+async fn focus_last(yabai: &Socket, window_id: &u32, state: &mut State) -> Result<()> {
+    let was_focused = state.focus_history.current() == Some(*window_id);
+    state.focus_history.remove(*window_id);
+    if !was_focused {
+        return Ok(());
+    }
 
-    if current_window.is_some() {
-        yabai
-            .execute(&[
-                "window".to_string(),
-                "--focus".into(),
-                format!("{last_window_id}"),
-            ])
-            .await?
+    let windows = yabai.windows("current").await?;
+    if windows.iter().any(|w| w.has_focus && w.id != *window_id) {
+        tracing::debug!("Current space kept focus, not restoring it");
+        return Ok(());
     }
-    Ok(())
+
+    focus::restore(yabai, state, Some(*window_id)).await
 }
 
 async fn resized(_yabai: &Socket, _window_id: &u32) -> Result<()> {
@@ -56,27 +78,102 @@ async fn moved(_yabai: &Socket, _window_id: &u32) -> Result<()> {
     Ok(())
 }
 
-async fn focused(yabai: &Socket, _window_id: &u32, config: &Config) -> Result<()> {
-    let last_window = yabai.last_window().await?;
-    let is_scratchpad = config
-        .scratchpads()
-        .iter()
-        .find(|sp| {
-            if sp.kind.is_app() {
-                last_window.app == sp.target
-            } else {
-                last_window.title == sp.target
-            }
-        })
-        .is_some();
+async fn focused(yabai: &Socket, window_id: &u32, state: &mut State) -> Result<()> {
+    // The focused window always belongs to the focused space.
+    let space = yabai.focused_space().await?;
+    state.focus_history.focused(*window_id, space.id);
 
-    if is_scratchpad {
+    let last_window = yabai.last_window().await?;
+    if state.config.is_scratchpad(&last_window) {
         window_hide_current().await?;
     }
 
-    Ok(())
+    monocle::focused(yabai, window_id, state).await
 }
 
-async fn created(_yabai: &Socket, _window_id: &u32) -> Result<()> {
+/// Move newly created window to the next space with room when its space is full.
+async fn created(yabai: &Socket, window: &Window, state: &mut State) -> Result<()> {
+    let config = &state.config;
+    if !is_tiled(window, config) {
+        return Ok(());
+    }
+
+    let snapshot = yabai.snapshot().await?;
+    let spaces = snapshot.spaces();
+    let tiled_count = |space: &Space| {
+        snapshot
+            .windows_in(space)
+            .filter(|w| is_tiled(w, config))
+            .count()
+    };
+    let has_room = |space: &Space| {
+        state
+            .max_windows(space)
+            .is_none_or(|max| tiled_count(space) < max)
+    };
+
+    let Some(space) = snapshot.space_of(window) else {
+        return Ok(());
+    };
+
+    match state.max_windows(space) {
+        Some(max) if tiled_count(space) > max => {
+            tracing::info!("Space {} exceeds {max} windows", space.index)
+        }
+        _ => return Ok(()),
+    };
+
+    // Look for the next space with room, wrapping around.
+    let (after, before): (Vec<&Space>, Vec<&Space>) =
+        spaces.iter().partition(|s| s.index > space.index);
+    let target = after
+        .into_iter()
+        .chain(before)
+        .filter(|s| s.id != space.id && !s.is_native_fullscreen)
+        .find(|s| has_room(s))
+        .map(|s| s.index);
+
+    let target = match target {
+        Some(target) => target,
+        None => {
+            yabai
+                .with_timeout(SPACE_CREATE_TIMEOUT)
+                .run(&Command::space(SpaceAction::Create))
+                .await?;
+            yabai
+                .spaces("all")
+                .await?
+                .into_iter()
+                .find(|s| spaces.iter().all(|o| o.id != s.id))
+                .map(|s| s.index)
+                .context("Unable to find created space")?
+        }
+    };
+
+    let window_sel = WindowSelector::Id(window.id);
+    let space_sel = SpaceSelector::Index(target);
+    tracing::info!("Moving {} to space {target}", window.title);
+    yabai
+        .run(&Command::window_at(
+            window_sel,
+            WindowAction::Space(space_sel),
+        ))
+        .await?;
+
+    if *config.follow_overflow() {
+        yabai
+            .run(&Command::window(WindowAction::Focus(window_sel)))
+            .await?;
+    }
+
     Ok(())
 }
+
+/// Whether the window takes part in the space tiling.
+fn is_tiled(window: &Window, config: &Config) -> bool {
+    !window.is_floating
+        && !window.is_sticky
+        && !window.is_minimized
+        && !window.is_hidden
+        && !config.is_scratchpad(window)
+}
@@ -1,48 +1,96 @@
 use crate::config::Config;
+use crate::matcher::Matcher;
 use crate::state::SharedState;
-use crate::util::window_hide_current;
-use crate::yabai::Socket;
-use anyhow::{bail, Result};
+use crate::subscription::Update;
+use crate::util::{launch, spawn, window_hide_current};
+use crate::yabai::{Command, RuleAction, Socket};
+use anyhow::{bail, Error, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tokio::process::Command;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum TargetKind {
     Title,
     App,
 }
 
-impl TargetKind {
-    pub fn is_app(&self) -> bool {
-        matches!(self, Self::App)
-    }
+/// Scratchpad definition as written by the user.
+///
+/// `kind` and `target` are kept for backward compatibility and are treated as an exact
+/// match on either the app name or the title.
+#[derive(Deserialize, JsonSchema)]
+struct ScratchpadDef {
+    tag: String,
+    kind: Option<TargetKind>,
+    target: Option<String>,
+    #[serde(rename = "match")]
+    matcher: Option<Matcher>,
+    command: Vec<String>,
+    timeout: Option<u8>,
+    space: Option<u8>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "ScratchpadDef")]
 pub struct Scratchpad {
     pub tag: String,
-    pub kind: TargetKind,
-    pub target: String,
+    #[serde(rename = "match")]
+    pub matcher: Matcher,
     pub command: Vec<String>,
+    /// Seconds to wait for the scratchpad window to show up after running `command`, the
+    /// toggle returns right away when unset.
     pub timeout: Option<u8>,
     pub space: Option<u8>,
 }
 
-impl Scratchpad {
-    pub fn configure_args(&self, config: &Config) -> Vec<String> {
-        let target = if self.kind.is_app() {
-            format!("app=^{}$", self.target)
-        } else {
-            format!("title=^{}$", self.target)
+impl TryFrom<ScratchpadDef> for Scratchpad {
+    type Error = Error;
+
+    fn try_from(def: ScratchpadDef) -> Result<Self> {
+        let matcher = match (def.matcher, def.kind, def.target) {
+            (Some(matcher), None, None) => matcher,
+            (None, Some(TargetKind::App), Some(target)) => Matcher::app_exact(&target),
+            (None, Some(TargetKind::Title), Some(target)) => Matcher::title_exact(&target),
+            (Some(_), _, _) => bail!("{}: use either match or kind/target, not both", def.tag),
+            _ => bail!("{}: expected match or both kind and target", def.tag),
         };
-        vec![
-            "rule".into(),
-            "--add".into(),
-            target,
-            format!("grid={}", config.scratchpad_grid()),
-            "manage=off".into(),
-        ]
+        matcher.validate()?;
+        if matcher.rule_args().is_empty() {
+            bail!(
+                "{}: match needs app, title, role or subrole for the yabai rule",
+                def.tag
+            );
+        }
+
+        if def.command.is_empty() {
+            bail!("{}: command can't be empty", def.tag);
+        }
+
+        Ok(Self {
+            tag: def.tag,
+            matcher,
+            command: def.command,
+            timeout: def.timeout,
+            space: def.space,
+        })
+    }
+}
+
+impl Scratchpad {
+    /// Label of the yabai rule managing the scratchpad.
+    pub fn rule_label(&self) -> String {
+        format!("yctrl_scratchpad_{}", self.tag)
+    }
+
+    pub fn configure_command(&self, config: &Config) -> Command {
+        Command::Rule(RuleAction::Add {
+            label: Some(self.rule_label()),
+            filters: self.matcher.rule_args(),
+            grid: Some(*config.scratchpad_grid()),
+            manage: Some(false),
+        })
     }
 }
 
@@ -51,10 +99,8 @@ pub struct ScratchpadEvent;
 impl ScratchpadEvent {
     pub async fn toggle(state: SharedState, tag: &str) -> Result<()> {
         let yabai = Socket::new()?;
-        let config = &state.lock().await.config;
-        let sp = if let Some(sp) = config.scratchpad_by_tag(tag) {
-            sp
-        } else {
+        // Cloned so the state isn't locked while possibly waiting for the scratchpad to launch.
+        let Some(sp) = state.lock().await.config.scratchpad_by_tag(tag).cloned() else {
             bail!("No Scratchpad with given tag: {tag}");
         };
         tracing::debug!("{sp:#?}");
@@ -66,19 +112,23 @@ impl ScratchpadEvent {
 
         tracing::info!("Focused on {:#?}", window);
 
-        let target = if sp.kind.is_app() {
-            window.app
-        } else {
-            window.title
+        // A focused scratchpad gets hidden, otherwise it's shown.
+        let visible = !sp.matcher.matches(&window);
+        if visible {
+            match sp.timeout {
+                Some(secs) => {
+                    let timeout = Duration::from_secs(secs.into());
+                    launch(&yabai, &sp.command, &sp.matcher, timeout).await?;
+                }
+                None => spawn(&sp.command)?,
+            }
         };
 
-        if target != sp.target {
-            let mut args = sp.command.clone();
-            let cmd = args.remove(0);
-            tracing::info!("running: {:?} with {:?}", cmd, args);
-            Command::new(cmd).args(args).spawn()?;
+        let update = Update::Scratchpad {
+            tag: sp.tag.clone(),
+            visible,
         };
-
+        state.lock().await.subscribers.publish(update);
         Ok(())
     }
 }
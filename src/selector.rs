@@ -0,0 +1,197 @@
+use crate::matcher::Matcher;
+use crate::runtime;
+use crate::yabai::{Snapshot, Socket, SpaceSelector, Window, WindowSelector};
+use anyhow::{bail, Context, Error, Result};
+use std::str::FromStr;
+
+/// Window selector resolved by yctrl before reaching yabai.
+///
+/// `app:<name>` and `title:<title>` match exactly, `app:/<regex>/` and `title:/<regex>/`
+/// match patterns, `mru:<n>` is the window focused `n` times ago. Anything else is a yabai
+/// window selector.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowTarget {
+    Yabai(WindowSelector),
+    Match(Matcher),
+    Mru(usize),
+}
+
+/// Space selector resolved by yctrl before reaching yabai.
+///
+/// `label:<label>` selects by label (same as `<label>`), `empty` the next space without
+/// windows. Anything else is a yabai space selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpaceTarget {
+    Yabai(SpaceSelector),
+    Empty,
+}
+
+impl WindowTarget {
+    /// Resolve to a yabai selector.
+    ///
+    /// When several windows match, the one following the focused window is selected, so
+    /// repeated invocations cycle through them.
+    pub async fn resolve(&self, yabai: &Socket) -> Result<WindowSelector> {
+        match self {
+            Self::Yabai(select) => Ok(*select),
+            Self::Mru(n) => {
+                let id = runtime::request(&["window", "mru", &n.to_string()]).await?;
+                Ok(WindowSelector::Id(id.trim().parse()?))
+            }
+            Self::Match(matcher) => next_match(&yabai.snapshot().await?, matcher)
+                .map(|w| WindowSelector::Id(w.id))
+                .context("No matching window"),
+        }
+    }
+}
+
+impl SpaceTarget {
+    /// Resolve to a yabai selector, cycling through matching spaces like [`WindowTarget`].
+    pub async fn resolve(&self, yabai: &Socket) -> Result<SpaceSelector> {
+        match self {
+            Self::Yabai(select) => Ok(select.clone()),
+            Self::Empty => {
+                let snapshot = yabai.snapshot().await?;
+                let indexes = snapshot
+                    .spaces()
+                    .iter()
+                    .filter(|s| {
+                        !s.is_native_fullscreen && !snapshot.windows_in(s).any(|w| w.is_shown())
+                    })
+                    .map(|s| s.index)
+                    .collect::<Vec<_>>();
+                let focused = snapshot.focused_space().map(|s| s.index);
+                next_after(&indexes, focused)
+                    .map(SpaceSelector::Index)
+                    .context("No empty space")
+            }
+        }
+    }
+}
+
+/// Window satisfying `matcher`, the one following the focused window when several do.
+///
+/// Windows are ordered by space, so repeated invocations go through spaces in order.
+pub fn next_match<'a>(snapshot: &'a Snapshot, matcher: &Matcher) -> Option<&'a Window> {
+    let mut matches = snapshot
+        .windows()
+        .iter()
+        .filter(|w| w.is_shown() && matcher.matches(w))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|w| (w.space, w.id));
+    let ids = matches.iter().map(|w| w.id).collect::<Vec<_>>();
+    let id = next_after(&ids, snapshot.focused_window().map(|w| w.id))?;
+    matches.into_iter().find(|w| w.id == id)
+}
+
+/// Parse an `app:<name>` or `title:<title>` window matcher (see [`WindowTarget`]).
+pub fn parse_matcher(s: &str) -> Result<Matcher> {
+    match s.parse()? {
+        WindowTarget::Match(matcher) => Ok(matcher),
+        _ => bail!("Invalid matcher {s}, expected app:<name> or title:<title>"),
+    }
+}
+
+/// Item following `current`, wrapping around, or the first one if `current` isn't in `items`.
+fn next_after<T: PartialEq + Copy>(items: &[T], current: Option<T>) -> Option<T> {
+    match items.iter().position(|i| Some(*i) == current) {
+        Some(position) => items.get((position + 1) % items.len()).copied(),
+        None => items.first().copied(),
+    }
+}
+
+/// Regex of a `/<regex>/` value.
+fn regex(value: &str) -> Option<&str> {
+    value.strip_prefix('/').and_then(|v| v.strip_suffix('/'))
+}
+
+impl FromStr for WindowTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let matcher = match s.split_once(':') {
+            Some((_, "")) => bail!("Invalid window selector {s}"),
+            Some(("app", app)) => match regex(app) {
+                Some(regex) => Matcher {
+                    app: Some(regex.parse()?),
+                    ..Default::default()
+                },
+                None => Matcher::app_exact(app),
+            },
+            Some(("title", title)) => match regex(title) {
+                Some(regex) => Matcher {
+                    title: Some(regex.parse()?),
+                    ..Default::default()
+                },
+                None => Matcher::title_exact(title),
+            },
+            Some(("mru", n)) => {
+                let n = n.parse().with_context(|| format!("Invalid count: {n}"))?;
+                return Ok(Self::Mru(n));
+            }
+            _ => return Ok(Self::Yabai(s.parse()?)),
+        };
+        matcher.validate()?;
+        Ok(Self::Match(matcher))
+    }
+}
+
+impl FromStr for SpaceTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "empty" => Ok(Self::Empty),
+            s => match s.strip_prefix("label:") {
+                Some("") => bail!("Missing label"),
+                Some(label) => Ok(Self::Yabai(SpaceSelector::Label(label.to_string()))),
+                None => Ok(Self::Yabai(s.parse()?)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_window_target() {
+        assert_eq!(
+            "app:Firefox".parse::<WindowTarget>().unwrap(),
+            WindowTarget::Match(Matcher::app_exact("Firefox"))
+        );
+        let WindowTarget::Match(matcher) = "title:/PR #[0-9]+/".parse().unwrap() else {
+            panic!("title isn't a matcher");
+        };
+        assert_eq!(matcher.title.unwrap().as_str(), "PR #[0-9]+");
+        assert!(r"title:/PR #\d+/".parse::<WindowTarget>().is_err());
+        assert_eq!(
+            "mru:2".parse::<WindowTarget>().unwrap(),
+            WindowTarget::Mru(2)
+        );
+        assert_eq!(
+            "largest".parse::<WindowTarget>().unwrap(),
+            WindowTarget::Yabai(WindowSelector::Largest)
+        );
+        assert!("title:/(/".parse::<WindowTarget>().is_err());
+        assert!("app:".parse::<WindowTarget>().is_err());
+    }
+
+    #[test]
+    fn parse_space_target() {
+        assert_eq!(
+            "label:web".parse::<SpaceTarget>().unwrap(),
+            SpaceTarget::Yabai(SpaceSelector::Label("web".into()))
+        );
+        assert_eq!("empty".parse::<SpaceTarget>().unwrap(), SpaceTarget::Empty);
+    }
+
+    #[test]
+    fn cycle_matches() {
+        assert_eq!(next_after(&[1, 2, 3], Some(2)), Some(3));
+        assert_eq!(next_after(&[1, 2, 3], Some(3)), Some(1));
+        assert_eq!(next_after(&[1, 2, 3], Some(7)), Some(1));
+        assert_eq!(next_after::<u32>(&[], None), None);
+    }
+}
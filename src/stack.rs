@@ -0,0 +1,190 @@
+use crate::output;
+use crate::yabai::{self, Command, Socket, StackSelector, Window, WindowAction, WindowSelector};
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+
+/// Stacked windows grouped by stack, ordered by space and position, each stack ordered by
+/// stack index.
+///
+/// yabai doesn't tell which stack a window belongs to, windows of a stack share the same
+/// frame in the same space.
+pub fn group(windows: &[Window]) -> Vec<Vec<&Window>> {
+    let mut stacks: Vec<Vec<&Window>> = vec![];
+    for window in windows.iter().filter(|w| w.stack_index > 0) {
+        let stack = stacks
+            .iter_mut()
+            .find(|s| s[0].space == window.space && s[0].frame == window.frame);
+        match stack {
+            Some(stack) => stack.push(window),
+            None => stacks.push(vec![window]),
+        }
+    }
+    stacks.sort_by(|a, b| {
+        let key = |w: &Window| (w.space, w.frame.x, w.frame.y);
+        key(a[0]).partial_cmp(&key(b[0])).unwrap_or(Ordering::Equal)
+    });
+    for stack in &mut stacks {
+        stack.sort_by_key(|w| w.stack_index);
+    }
+    stacks
+}
+
+/// Stacks as a table, one line per window.
+pub fn table(stacks: &[Vec<&Window>]) -> String {
+    let header = ["SPACE", "STACK", "INDEX", "ID", "APP", "TITLE", "FOCUS"];
+    let mut rows = vec![header.map(String::from).to_vec()];
+    for (i, stack) in stacks.iter().enumerate() {
+        for window in stack {
+            rows.push(vec![
+                window.space.to_string(),
+                (i + 1).to_string(),
+                window.stack_index.to_string(),
+                window.id.to_string(),
+                window.app.clone(),
+                window.title.clone(),
+                if window.has_focus { "f" } else { "-" }.to_string(),
+            ]);
+        }
+    }
+    output::table(&rows)
+}
+
+/// Stack end to wrap around to when `select` goes past the end of the stack, yabai fails
+/// there.
+pub fn wrap(select: WindowSelector) -> Option<WindowSelector> {
+    match select {
+        WindowSelector::Stack(StackSelector::Next) => {
+            Some(WindowSelector::Stack(StackSelector::First))
+        }
+        WindowSelector::Stack(StackSelector::Prev) => {
+            Some(WindowSelector::Stack(StackSelector::Last))
+        }
+        _ => None,
+    }
+}
+
+/// Window of the focused stack following the focused one when moving towards `select`.
+fn step(select: WindowSelector) -> StackSelector {
+    match select {
+        WindowSelector::Next => StackSelector::Next,
+        _ => StackSelector::Prev,
+    }
+}
+
+/// Window of a stack focus enters when moving towards `select`.
+fn entry(select: WindowSelector) -> StackSelector {
+    match select {
+        WindowSelector::Next => StackSelector::First,
+        _ => StackSelector::Last,
+    }
+}
+
+/// Take the window out of its stack, by floating it and tiling it again.
+pub async fn unstack(yabai: &Socket, id: Option<u32>) -> Result<()> {
+    let window = match id {
+        Some(id) => yabai.window(&id).await?,
+        None => yabai.focused_window().await?,
+    };
+    if window.stack_index == 0 {
+        bail!("Window {} isn't stacked", window.id);
+    }
+
+    let toggle = Command::window_at(WindowSelector::Id(window.id), WindowAction::ToggleFloat);
+    yabai.run(&toggle).await?;
+    yabai.run(&toggle).await
+}
+
+/// Focus the next (or previous) window of the focused stack, false past its end.
+pub async fn focus_in_stack(yabai: &Socket, select: WindowSelector) -> Result<bool> {
+    if !focus_is_stacked(yabai).await? {
+        return Ok(false);
+    }
+
+    let focus = WindowAction::Focus(WindowSelector::Stack(step(select)));
+    match yabai.run(&Command::window(focus)).await {
+        Ok(()) => Ok(true),
+        Err(e) if yabai::Error::is_rejection(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Focus the first (or last, when going backward) window of the stack focus moved to.
+pub async fn enter_stack(yabai: &Socket, select: WindowSelector) -> Result<()> {
+    if !focus_is_stacked(yabai).await? {
+        return Ok(());
+    }
+
+    let focus = WindowAction::Focus(WindowSelector::Stack(entry(select)));
+    yabai.run(&Command::window(focus)).await
+}
+
+async fn focus_is_stacked(yabai: &Socket) -> Result<bool> {
+    match yabai.focused_window().await {
+        Ok(window) => Ok(window.stack_index > 0),
+        // No window has focus.
+        Err(e) if yabai::Error::is_rejection(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yabai::Frame;
+
+    fn window(id: u32, space: u32, x: f32, stack_index: u32) -> Window {
+        Window {
+            id,
+            space,
+            stack_index,
+            frame: Frame {
+                x,
+                y: 0.0,
+                w: 100.0,
+                h: 100.0,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn group_by_space_and_frame() {
+        let windows = [
+            window(1, 2, 0.0, 2),
+            window(2, 1, 100.0, 1),
+            window(3, 2, 0.0, 1),
+            window(4, 1, 0.0, 1),
+            window(5, 1, 0.0, 2),
+            // Same frame as 4 and 5, in another space.
+            window(6, 2, 100.0, 1),
+            // Not stacked.
+            window(7, 1, 0.0, 0),
+        ];
+
+        let ids = group(&windows)
+            .iter()
+            .map(|s| s.iter().map(|w| w.id).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [vec![4, 5], vec![2], vec![3, 1], vec![6]]);
+    }
+
+    #[test]
+    fn stack_selectors() {
+        let stack = WindowSelector::Stack;
+        assert_eq!(
+            wrap(stack(StackSelector::Next)),
+            Some(stack(StackSelector::First))
+        );
+        assert_eq!(
+            wrap(stack(StackSelector::Prev)),
+            Some(stack(StackSelector::Last))
+        );
+        assert_eq!(wrap(stack(StackSelector::First)), None);
+        assert_eq!(wrap(WindowSelector::Next), None);
+
+        assert_eq!(step(WindowSelector::Next), StackSelector::Next);
+        assert_eq!(step(WindowSelector::Prev), StackSelector::Prev);
+        assert_eq!(entry(WindowSelector::Next), StackSelector::First);
+        assert_eq!(entry(WindowSelector::Prev), StackSelector::Last);
+    }
+}
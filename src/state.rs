@@ -1,52 +1,135 @@
-use crate::{config::Config, yabai};
-use anyhow::{bail, Result};
+use crate::history::{FocusHistory, SpaceHistory};
+use crate::hook::HookRunner;
+use crate::logging::FilterHandle;
+use crate::profile::SpaceProfile;
+use crate::subscription::{Subscribers, Update};
+use crate::yabai::{Command, RuleAction, Space, Zoom};
+use crate::{config::Config, label, yabai};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+#[derive(Default)]
 pub struct State {
     pub config: Config,
-    pub scratchpad_open: bool,
-}
-
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            config: Config::default(),
-            scratchpad_open: false,
-        }
-    }
+    /// Profiles switched on the fly, by space id.
+    pub space_profiles: HashMap<u32, String>,
+    /// Profiles last applied to spaces, by space id.
+    pub applied_profiles: HashMap<u32, SpaceProfile>,
+    /// Most recently focused windows.
+    pub focus_history: FocusHistory,
+    /// Most recently focused spaces.
+    pub space_history: SpaceHistory,
+    /// Limits hooks running concurrently.
+    pub hook_runner: HookRunner,
+    /// Terminal windows swallowed, by the id of the window that replaced them.
+    pub swallowed: HashMap<u32, u32>,
+    /// Spaces in monocle mode, by space id.
+    pub monocles: HashMap<u32, Monocle>,
+    /// Clients streaming updates.
+    pub subscribers: Subscribers,
+    /// Log filter, reloaded with `yctrl daemon log-level`.
+    pub log_filter: Option<FilterHandle>,
 }
 
 pub type SharedState = Arc<Mutex<State>>;
 
+/// Monocle mode of a space, every focused window is zoomed to fullscreen.
+#[derive(Debug, Default)]
+pub struct Monocle {
+    /// Zoom of windows before monocle mode zoomed them, by window id.
+    pub zoom: HashMap<u32, Zoom>,
+}
+
 impl State {
-    pub async fn handle(&mut self, mut args: Vec<&str>) -> Result<()> {
-        let key = args.remove(0);
-        let value = args.get(1).unwrap();
-        let yabai = yabai::Socket::new()?;
+    /// Handle config request and return the response to send back to the client.
+    pub async fn handle(&mut self, mut args: Vec<&str>) -> Result<String> {
+        if args.is_empty() {
+            bail!("Missing config key");
+        }
 
+        let key = args.remove(0);
         match key {
-            "yctrl_auto_close_empty_spaces" => {
-                self.config.set_auto_close_empty_spaces_with_str(value)?;
-            }
-            "yctrl_scratchpad_grid" => {
-                self.config.set_scratchpad_grid_with_str(value)?;
-            }
-            "yctrl_scratchpads" => {
-                self.config.set_scratchpads_with_str(&args.join(" "))?;
-                for configure_args in self
-                    .config
-                    .scratchpads()
-                    .iter()
-                    .map(|sp| sp.configure_args(&self.config))
-                {
-                    yabai.request(&configure_args).await?;
-                }
-            }
+            "--list" => return self.config.list(),
+            "--get" => match args.first() {
+                Some(key) => return self.config.get(key),
+                None => bail!("Missing config key for --get"),
+            },
+            _ if args.is_empty() => return self.config.get(key),
+            _ => (),
+        }
+
+        self.config.set(key, &args.join(" "))?;
+        if key.ends_with("scratchpads") {
+            self.configure_scratchpads().await?;
+        } else if key.ends_with("space_labels") {
+            label::apply(&yabai::Socket::new()?, self.config.space_labels()).await?;
+        }
+
+        Ok(String::default())
+    }
+
+    /// Profile of the given space, either switched to on the fly or named after its label.
+    pub fn space_profile(&self, space: &Space) -> Option<&SpaceProfile> {
+        let name = self.space_profiles.get(&space.id).unwrap_or(&space.label);
+        self.config.space_profile(name)
+    }
+
+    /// Maximum number of tiled windows allowed in the given space.
+    pub fn max_windows(&self, space: &Space) -> Option<usize> {
+        self.space_profile(space)
+            .and_then(|p| p.max_windows)
+            .or(self.config.max_windows())
+    }
 
-            _ => bail!("Unknown config key {key}"),
+    /// Switch current space to the profile called `name`.
+    pub async fn switch_space_profile(&mut self, name: &str) -> Result<()> {
+        let Some(profile) = self.config.space_profile(name).cloned() else {
+            bail!("No space profile named {name}");
+        };
+
+        let yabai = yabai::Socket::new()?;
+        let space = yabai.focused_space().await?;
+        let applied = self.applied_profiles.get(&space.id);
+        if let Some(layout) = profile.apply(&yabai, &space, applied).await? {
+            let space = space.index;
+            self.subscribers.publish(Update::Layout { space, layout });
         }
+        self.applied_profiles.insert(space.id, profile);
+        self.space_profiles.insert(space.id, name.to_string());
 
         Ok(())
     }
+
+    /// Load configuration file if it exists.
+    pub async fn load_config_file(&mut self) -> Result<()> {
+        let Some(path) = Config::path().filter(|p| p.exists()) else {
+            return Ok(());
+        };
+
+        let content = tokio::fs::read_to_string(&path).await?;
+        self.config = Config::parse(&content)
+            .with_context(|| format!("Invalid configuration file {}", path.display()))?;
+        tracing::info!("Loaded configuration from {}", path.display());
+
+        self.configure_scratchpads().await?;
+        label::apply(&yabai::Socket::new()?, self.config.space_labels()).await
+    }
+
+    /// Register yabai rules for configured scratchpads, replacing previous ones.
+    async fn configure_scratchpads(&self) -> Result<()> {
+        let yabai = yabai::Socket::new()?;
+        let rules = yabai.rules().await?;
+        for sp in self.config.scratchpads() {
+            let label = sp.rule_label();
+            if rules.iter().any(|r| r.label == label) {
+                yabai.run(&Command::Rule(RuleAction::Remove(label))).await?;
+            }
+            yabai
+                .request(&sp.configure_command(&self.config).args())
+                .await?;
+        }
+        Ok(())
+    }
 }
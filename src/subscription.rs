@@ -0,0 +1,103 @@
+use crate::yabai::{Event, Layout};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+
+/// Topics that can be subscribed to.
+pub const TOPICS: &[&str] = &[
+    "window",
+    "space",
+    "display",
+    "application",
+    "mission_control",
+    "scratchpad",
+    "layout",
+];
+
+/// Updates buffered for each subscriber, slower ones miss older updates.
+const CAPACITY: usize = 64;
+
+/// State change streamed to subscribers, one JSON object per line.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Update {
+    /// yabai event processed by the daemon.
+    Event {
+        event: &'static str,
+        #[serde(flatten)]
+        ids: BTreeMap<&'static str, u32>,
+    },
+    /// Scratchpad shown or hidden.
+    Scratchpad { tag: String, visible: bool },
+    /// Layout of the space at `space` index changed.
+    Layout { space: u32, layout: Layout },
+}
+
+impl Update {
+    /// Whether the update belongs to one of `topics`, all updates match no topics.
+    pub fn matches(&self, topics: &[String]) -> bool {
+        let topic = match self {
+            Update::Event { event, .. } => TOPICS
+                .iter()
+                .find(|t| event.starts_with(*t))
+                .copied()
+                .unwrap_or(event),
+            Update::Scratchpad { .. } => "scratchpad",
+            Update::Layout { .. } => "layout",
+        };
+        topics.is_empty() || topics.iter().any(|t| t == topic)
+    }
+}
+
+impl From<&Event> for Update {
+    fn from(event: &Event) -> Self {
+        Update::Event {
+            event: event.name(),
+            ids: event.ids().into_iter().collect(),
+        }
+    }
+}
+
+/// Clients subscribed to updates.
+#[derive(Debug)]
+pub struct Subscribers(broadcast::Sender<Update>);
+
+impl Default for Subscribers {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl Subscribers {
+    /// Send `update` to current subscribers, if any.
+    pub fn publish(&self, update: Update) {
+        tracing::trace!("Publishing {update:?}");
+        self.0.send(update).ok();
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Update> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yabai::SpaceEvent;
+
+    #[test]
+    fn match_topics() {
+        let event = Update::from(&Event::Space(SpaceEvent::Changed {
+            space_id: 2,
+            recent_space_id: 1,
+        }));
+        assert!(event.matches(&[]));
+        assert!(event.matches(&["window".into(), "space".into()]));
+        assert!(!event.matches(&["scratchpad".into()]));
+
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"type":"event","event":"space_changed","recent_space_id":1,"space_id":2}"#
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use crate::config::Config;
+use crate::runtime;
+use crate::state::State;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+
+/// Trace file being recorded to, if any.
+static RECORDER: Mutex<Option<File>> = Mutex::new(None);
+
+/// Replays started so far.
+static REPLAYS: AtomicUsize = AtomicUsize::new(0);
+
+tokio::task_local! {
+    /// Trace the task replays, if any.
+    static REPLAY: Replay;
+}
+
+/// Leading byte of a response signaling that the command failed (same as yabai).
+const FAILURE_MESSAGE: u8 = 0x07;
+
+/// Line of a trace file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// Milliseconds since the Unix epoch.
+    time: u64,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Entry {
+    /// Configuration of the daemon when recording started.
+    Config { config: Config },
+    /// Request received by the daemon.
+    Request { request: String },
+    /// Request sent to yabai along with its raw response, missing if the exchange failed.
+    Yabai {
+        args: Vec<String>,
+        response: Option<String>,
+    },
+    /// Parent process ids of a process, closest first, as looked up to find the terminal a
+    /// window was launched from.
+    Ancestors { pid: u32, ancestors: Vec<u32> },
+}
+
+/// What a trace replay serves in place of the host.
+#[derive(Debug, Default)]
+struct Replay {
+    /// Path of the fake yabai socket.
+    socket: String,
+    /// Recorded parent process ids, by process id.
+    ancestors: HashMap<u32, Vec<u32>>,
+}
+
+/// Record to the file at `path`, starting with the given configuration.
+pub fn start(path: &Path, config: &Config) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Unable to create trace file {}", path.display()))?;
+    *RECORDER.lock().unwrap() = Some(file);
+    record(Entry::Config {
+        config: config.clone(),
+    });
+    tracing::info!("Recording trace to {}", path.display());
+    Ok(())
+}
+
+/// Whether a trace is being recorded.
+pub fn enabled() -> bool {
+    RECORDER.lock().unwrap().is_some()
+}
+
+/// Append `entry` to the trace, if recording.
+pub fn record(entry: Entry) {
+    let mut recorder = RECORDER.lock().unwrap();
+    let Some(file) = recorder.as_mut() else {
+        return;
+    };
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let written = serde_json::to_string(&Record { time, entry })
+        .map_err(anyhow::Error::new)
+        .and_then(|line| Ok(writeln!(file, "{line}")?));
+    if let Err(e) = written {
+        tracing::error!("Unable to record trace, stopping: {e:?}");
+        *recorder = None;
+    }
+}
+
+/// Fake yabai socket to use instead of the real one, while replaying a trace.
+pub fn replay_socket() -> Option<String> {
+    REPLAY.try_with(|replay| replay.socket.clone()).ok()
+}
+
+/// Whether a trace is being replayed, during which no external program is run (hooks,
+/// scratchpad commands...) so that replaying doesn't affect the host.
+pub fn replaying() -> bool {
+    REPLAY.try_with(|_| ()).is_ok()
+}
+
+/// Parent process ids of `pid` recorded in the trace being replayed.
+pub fn replayed_ancestors(pid: u32) -> Option<Vec<u32>> {
+    REPLAY
+        .try_with(|replay| replay.ancestors.get(&pid).cloned())
+        .ok()
+        .flatten()
+}
+
+/// Recorded yabai exchanges served by the fake yabai socket.
+#[derive(Debug, Default)]
+struct Exchanges {
+    /// Recorded exchanges, emptied once replayed.
+    recorded: Vec<Option<(Vec<String>, Option<String>)>>,
+    /// Requests that weren't recorded.
+    unexpected: Vec<Vec<String>>,
+}
+
+impl Exchanges {
+    /// Response to the first recorded exchange with the same arguments.
+    fn respond(&mut self, args: Vec<String>) -> Option<Vec<u8>> {
+        let exchange = self
+            .recorded
+            .iter_mut()
+            .find(|e| e.as_ref().is_some_and(|(recorded, _)| recorded == &args));
+        match exchange.and_then(Option::take) {
+            Some((_, response)) => response.map(String::into_bytes),
+            None => {
+                let message = format!("yctrl replay: {args:?} wasn't recorded");
+                self.unexpected.push(args);
+                Some([&[FAILURE_MESSAGE], message.as_bytes()].concat())
+            }
+        }
+    }
+}
+
+/// Re-run requests of the trace at `path` against a fake yabai serving recorded responses.
+///
+/// Fails if the requests sent to yabai differ from the recorded ones. External programs
+/// aren't run, see [`replaying`].
+pub async fn replay(path: &Path) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Unable to read trace file {}", path.display()))?;
+    let records = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str::<Record>(line)
+                .with_context(|| format!("Invalid record {}", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut state = State::default();
+    let mut requests = vec![];
+    let mut exchanges = Exchanges::default();
+    let mut replay = Replay::default();
+    for record in records {
+        match record.entry {
+            Entry::Config { config } => state.config = config,
+            Entry::Request { request } => requests.push(request),
+            Entry::Yabai { args, response } => exchanges.recorded.push(Some((args, response))),
+            Entry::Ancestors { pid, ancestors } => _ = replay.ancestors.insert(pid, ancestors),
+        }
+    }
+
+    // Unique per replay, as tests replay traces concurrently.
+    let count = REPLAYS.fetch_add(1, Ordering::Relaxed);
+    let name = format!("yctrl-replay-{}-{count}.socket", std::process::id());
+    let socket_path = env::temp_dir().join(name);
+    fs::remove_file(&socket_path).ok();
+    let listener = UnixListener::bind(&socket_path)?;
+
+    let exchanges = Arc::new(Mutex::new(exchanges));
+    let server = tokio::spawn({
+        let exchanges = exchanges.clone();
+        async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut len = [0; 4];
+                stream.read_exact(&mut len).await?;
+                let mut message = vec![0; u32::from_le_bytes(len) as usize];
+                stream.read_exact(&mut message).await?;
+                let args = parse_args(&message);
+                let response = exchanges.lock().unwrap().respond(args);
+                if let Some(response) = response {
+                    stream.write_all(&response).await?;
+                }
+            }
+            Ok::<_, std::io::Error>(())
+        }
+    });
+
+    let state = Arc::new(tokio::sync::Mutex::new(state));
+    replay.socket = socket_path.to_string_lossy().into_owned();
+    REPLAY
+        .scope(replay, async {
+            for request in &requests {
+                match runtime::process(request, state.clone()).await {
+                    Ok(response) if response.is_empty() => println!("{request}: ok"),
+                    Ok(response) => println!("{request}: {response}"),
+                    Err(e) => println!("{request}: error: {e:#}"),
+                }
+            }
+        })
+        .await;
+    server.abort();
+    fs::remove_file(&socket_path).ok();
+
+    let exchanges = exchanges.lock().unwrap();
+    let missing = exchanges.recorded.iter().flatten().collect::<Vec<_>>();
+    for args in &exchanges.unexpected {
+        println!("unexpected yabai request: {args:?}");
+    }
+    for (args, _) in &missing {
+        println!("missing yabai request: {args:?}");
+    }
+    if !exchanges.unexpected.is_empty() || !missing.is_empty() {
+        bail!("Replay diverged from the trace");
+    }
+    Ok(())
+}
+
+/// Arguments of a message sent to yabai, empty ones included.
+///
+/// Each argument is terminated by a NUL, and so is the message.
+fn parse_args(message: &[u8]) -> Vec<String> {
+    let Some(message) = message.strip_suffix(&[0]) else {
+        return vec![];
+    };
+    let mut args = message
+        .split(|b| *b == 0)
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect::<Vec<_>>();
+    // Nothing follows the terminator of the last argument.
+    args.pop();
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/traces")
+            .join(format!("{name}.jsonl"))
+    }
+
+    #[tokio::test]
+    async fn replay_focus_back() {
+        replay(&fixture("focus-back")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_focus_restore_only_when_focus_lost() {
+        replay(&fixture("focus-restore")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_swallow_and_hooks() {
+        replay(&fixture("swallow-hooks")).await.unwrap();
+    }
+
+    #[test]
+    fn keep_empty_args() {
+        assert_eq!(
+            parse_args(b"space\0\x31\0--label\0\0\0"),
+            ["space", "1", "--label", ""]
+        );
+        assert!(parse_args(b"").is_empty());
+        assert!(parse_args(b"\0").is_empty());
+    }
+
+    #[test]
+    fn serve_recorded_responses() {
+        let args = |a: &[&str]| a.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut exchanges = Exchanges {
+            recorded: vec![
+                Some((args(&["query", "--spaces"]), Some("[1]".into()))),
+                Some((args(&["query", "--spaces"]), Some("[2]".into()))),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            exchanges.respond(args(&["query", "--spaces"])),
+            Some(b"[1]".to_vec())
+        );
+        assert_eq!(
+            exchanges.respond(args(&["query", "--spaces"])),
+            Some(b"[2]".to_vec())
+        );
+        assert!(exchanges.respond(args(&["query", "--spaces"])).unwrap()[0] == FAILURE_MESSAGE);
+        assert_eq!(exchanges.unexpected.len(), 1);
+    }
+}
@@ -1,7 +1,23 @@
-use anyhow::Result;
+use crate::matcher::Matcher;
+use crate::trace::{self, Entry};
+use crate::yabai::{Socket, Window};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tokio::process::Command;
+use tokio::time::sleep;
+
+/// Time given to a launched command to create its window, unless configured otherwise.
+pub const LAUNCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Interval between checks for the window of a launched command.
+const LAUNCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Depth at which looking for parent processes stops.
+const MAX_ANCESTORS: usize = 32;
 
 pub async fn window_hide_current() -> Result<()> {
+    if trace::replaying() {
+        return Ok(());
+    }
     let script = r#"tell application "System Events" to set visible of (item 1 of (processes whose frontmost is true)) to false"#;
     Command::new("osascript")
         .arg("-e")
@@ -11,3 +27,76 @@ pub async fn window_hide_current() -> Result<()> {
 
     Ok(())
 }
+
+/// Parent process ids of `pid`, closest first, stopping before launchd.
+///
+/// Recorded in traces, so that replaying picks the same terminal without listing processes.
+pub async fn ancestors(pid: u32) -> Result<Vec<u32>> {
+    if trace::replaying() {
+        return Ok(trace::replayed_ancestors(pid).unwrap_or_default());
+    }
+    // One listing of every process rather than a `ps` per ancestor.
+    let output = Command::new("ps")
+        .args(["-A", "-o", "pid=,ppid="])
+        .output()
+        .await?;
+    let parents = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut ids = line.split_whitespace().map(str::parse::<u32>);
+            Some((ids.next()?.ok()?, ids.next()?.ok()?))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut ancestors = vec![];
+    let mut child = pid;
+    while ancestors.len() < MAX_ANCESTORS {
+        match parents.get(&child) {
+            Some(&ppid) if ppid > 1 => {
+                ancestors.push(ppid);
+                child = ppid;
+            }
+            _ => break,
+        }
+    }
+    trace::record(Entry::Ancestors {
+        pid,
+        ancestors: ancestors.clone(),
+    });
+    Ok(ancestors)
+}
+
+/// Run `command` in the background.
+pub fn spawn(command: &[String]) -> Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("Missing command to launch");
+    };
+    tracing::info!("running: {:?} with {:?}", program, args);
+    match trace::replaying() {
+        true => tracing::info!("Not running {program} while replaying"),
+        false => _ = Command::new(program).args(args).spawn()?,
+    }
+    Ok(())
+}
+
+/// Run `command` and wait for a window satisfying `matcher` to show up.
+pub async fn launch(
+    yabai: &Socket,
+    command: &[String],
+    matcher: &Matcher,
+    timeout: Duration,
+) -> Result<Window> {
+    spawn(command)?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let windows = yabai.windows("all").await?;
+        if let Some(window) = windows.into_iter().find(|w| matcher.matches(w)) {
+            return Ok(window);
+        }
+        if Instant::now() >= deadline {
+            bail!("No window matching {matcher:?} after {timeout:?}");
+        }
+        sleep(LAUNCH_POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,619 @@
+use crate::config::Grid;
+use anyhow::{bail, Error, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Cardinal direction, as understood by yabai.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+/// Window selector: `prev|next|first|last|recent|mouse|largest|smallest|sibling|...|<id>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowSelector {
+    Prev,
+    Next,
+    First,
+    Last,
+    Recent,
+    Mouse,
+    Largest,
+    Smallest,
+    Sibling,
+    FirstNephew,
+    SecondNephew,
+    Uncle,
+    FirstCousin,
+    SecondCousin,
+    Direction(Direction),
+    Stack(StackSelector),
+    Id(u32),
+}
+
+/// Stack selector: `stack.prev|stack.next|stack.first|stack.last|stack.recent|stack.<index>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackSelector {
+    Prev,
+    Next,
+    First,
+    Last,
+    Recent,
+    Index(u32),
+}
+
+/// Space selector: `prev|next|first|last|recent|mouse|<index>|<label>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpaceSelector {
+    Prev,
+    Next,
+    First,
+    Last,
+    Recent,
+    Mouse,
+    Index(u32),
+    Label(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    Bsp,
+    Stack,
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Padding {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// Window handle to drag when resizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeHandle {
+    Top,
+    Left,
+    Bottom,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+    /// Absolute size rather than a relative offset.
+    Abs,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowAction {
+    Focus(WindowSelector),
+    Swap(WindowSelector),
+    Warp(WindowSelector),
+    Space(SpaceSelector),
+    Resize(ResizeHandle, i32, i32),
+    Minimize,
+    Deminimize,
+    Stack(WindowSelector),
+    ToggleFloat,
+    ToggleZoomParent,
+    ToggleZoomFullscreen,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpaceAction {
+    Focus(SpaceSelector),
+    Move(SpaceSelector),
+    Swap(SpaceSelector),
+    Create,
+    Destroy,
+    Label(String),
+    /// Remove the space label.
+    Unlabel,
+    Layout(Layout),
+    Padding(Padding),
+    Gap(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleAction {
+    /// Add a rule applied to windows matching all of `filters` (e.g. `app=^Alacritty$`).
+    Add {
+        label: Option<String>,
+        filters: Vec<String>,
+        grid: Option<Grid>,
+        manage: Option<bool>,
+    },
+    /// Remove rule by label.
+    Remove(String),
+}
+
+/// Command sent to yabai socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Act on the given window, or the focused one.
+    Window(Option<WindowSelector>, WindowAction),
+    /// Act on the given space, or the focused one.
+    Space(Option<SpaceSelector>, SpaceAction),
+    /// Add or remove a window rule.
+    Rule(RuleAction),
+}
+
+impl Command {
+    /// Act on the focused window.
+    pub fn window(action: WindowAction) -> Self {
+        Self::Window(None, action)
+    }
+
+    /// Act on the given window.
+    pub fn window_at(window: WindowSelector, action: WindowAction) -> Self {
+        Self::Window(Some(window), action)
+    }
+
+    /// Act on the focused space.
+    pub fn space(action: SpaceAction) -> Self {
+        Self::Space(None, action)
+    }
+
+    /// Act on the given space.
+    pub fn space_at(space: SpaceSelector, action: SpaceAction) -> Self {
+        Self::Space(Some(space), action)
+    }
+
+    /// Arguments as expected by yabai socket.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        match self {
+            Command::Window(window, action) => {
+                args.push("window".into());
+                args.extend(window.map(|w| w.to_string()));
+                match action {
+                    WindowAction::Focus(sel) => args.extend(["--focus".into(), sel.to_string()]),
+                    WindowAction::Swap(sel) => args.extend(["--swap".into(), sel.to_string()]),
+                    WindowAction::Warp(sel) => args.extend(["--warp".into(), sel.to_string()]),
+                    WindowAction::Space(sel) => args.extend(["--space".into(), sel.to_string()]),
+                    WindowAction::Resize(ResizeHandle::Abs, w, h) => {
+                        args.extend(["--resize".into(), format!("abs:{w}:{h}")])
+                    }
+                    WindowAction::Resize(handle, dx, dy) => {
+                        args.extend(["--resize".into(), format!("{handle}:{dx:+}:{dy:+}")])
+                    }
+                    WindowAction::Minimize => args.push("--minimize".into()),
+                    WindowAction::Deminimize => args.push("--deminimize".into()),
+                    WindowAction::Stack(sel) => args.extend(["--stack".into(), sel.to_string()]),
+                    WindowAction::ToggleFloat => args.extend(["--toggle".into(), "float".into()]),
+                    WindowAction::ToggleZoomParent => {
+                        args.extend(["--toggle".into(), "zoom-parent".into()])
+                    }
+                    WindowAction::ToggleZoomFullscreen => {
+                        args.extend(["--toggle".into(), "zoom-fullscreen".into()])
+                    }
+                }
+            }
+            Command::Space(space, action) => {
+                args.push("space".into());
+                args.extend(space.as_ref().map(|s| s.to_string()));
+                match action {
+                    SpaceAction::Focus(sel) => args.extend(["--focus".into(), sel.to_string()]),
+                    SpaceAction::Move(sel) => args.extend(["--move".into(), sel.to_string()]),
+                    SpaceAction::Swap(sel) => args.extend(["--swap".into(), sel.to_string()]),
+                    SpaceAction::Create => args.push("--create".into()),
+                    SpaceAction::Destroy => args.push("--destroy".into()),
+                    SpaceAction::Label(label) => args.extend(["--label".into(), label.clone()]),
+                    // yabai removes the label when `--label` has no argument.
+                    SpaceAction::Unlabel => args.push("--label".into()),
+                    SpaceAction::Layout(layout) => {
+                        args.extend(["--layout".into(), layout.to_string()])
+                    }
+                    SpaceAction::Padding(Padding {
+                        top,
+                        bottom,
+                        left,
+                        right,
+                    }) => args.extend([
+                        "--padding".into(),
+                        format!("abs:{top}:{bottom}:{left}:{right}"),
+                    ]),
+                    SpaceAction::Gap(gap) => args.extend(["--gap".into(), format!("abs:{gap}")]),
+                }
+            }
+            Command::Rule(RuleAction::Add {
+                label,
+                filters,
+                grid,
+                manage,
+            }) => {
+                args.extend(["rule".into(), "--add".into()]);
+                args.extend(label.as_ref().map(|label| format!("label={label}")));
+                args.extend(filters.iter().cloned());
+                args.extend(grid.map(|grid| format!("grid={grid}")));
+                args.extend(
+                    manage.map(|manage| format!("manage={}", if manage { "on" } else { "off" })),
+                );
+            }
+            Command::Rule(RuleAction::Remove(label)) => {
+                args.extend(["rule".into(), "--remove".into(), label.clone()]);
+            }
+        }
+        args
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.args().join(" "))
+    }
+}
+
+impl WindowSelector {
+    /// Whether the selector is relative to the focused window order (`next`/`prev`).
+    pub fn is_cyclic(&self) -> bool {
+        matches!(self, Self::Next | Self::Prev)
+    }
+}
+
+impl SpaceSelector {
+    /// Whether the selector is relative to the focused space order (`next`/`prev`).
+    pub fn is_cyclic(&self) -> bool {
+        matches!(self, Self::Next | Self::Prev)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "north" => Self::North,
+            "east" => Self::East,
+            "south" => Self::South,
+            "west" => Self::West,
+            _ => bail!("Invalid direction {s}, expected north, east, south or west"),
+        })
+    }
+}
+
+impl FromStr for StackSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "prev" => Self::Prev,
+            "next" => Self::Next,
+            "first" => Self::First,
+            "last" => Self::Last,
+            "recent" => Self::Recent,
+            s => match s.parse() {
+                Ok(index) => Self::Index(index),
+                Err(_) => bail!("Invalid stack selector stack.{s}"),
+            },
+        })
+    }
+}
+
+impl FromStr for WindowSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "prev" => Self::Prev,
+            "next" => Self::Next,
+            "first" => Self::First,
+            "last" => Self::Last,
+            "recent" => Self::Recent,
+            "mouse" => Self::Mouse,
+            "largest" => Self::Largest,
+            "smallest" => Self::Smallest,
+            "sibling" => Self::Sibling,
+            "first_nephew" => Self::FirstNephew,
+            "second_nephew" => Self::SecondNephew,
+            "uncle" => Self::Uncle,
+            "first_cousin" => Self::FirstCousin,
+            "second_cousin" => Self::SecondCousin,
+            s => {
+                if let Some(stack) = s.strip_prefix("stack.") {
+                    Self::Stack(stack.parse()?)
+                } else if let Ok(direction) = s.parse() {
+                    Self::Direction(direction)
+                } else if let Ok(id) = s.parse() {
+                    Self::Id(id)
+                } else {
+                    bail!("Invalid window selector {s}")
+                }
+            }
+        })
+    }
+}
+
+/// Space selectors that aren't indices or labels.
+pub const SPACE_KEYWORDS: [&str; 6] = ["prev", "next", "first", "last", "recent", "mouse"];
+
+impl FromStr for SpaceSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "prev" => Self::Prev,
+            "next" => Self::Next,
+            "first" => Self::First,
+            "last" => Self::Last,
+            "recent" => Self::Recent,
+            "mouse" => Self::Mouse,
+            s => match s.parse() {
+                Ok(index) => Self::Index(index),
+                Err(_) if s.is_empty() || s.contains(char::is_whitespace) => {
+                    bail!("Invalid space selector {s:?}")
+                }
+                Err(_) => Self::Label(s.to_string()),
+            },
+        })
+    }
+}
+
+impl FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "bsp" => Self::Bsp,
+            "stack" => Self::Stack,
+            "float" => Self::Float,
+            _ => bail!("Invalid layout {s}, expected bsp, stack or float"),
+        })
+    }
+}
+
+impl FromStr for ResizeHandle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "top" => Self::Top,
+            "left" => Self::Left,
+            "bottom" => Self::Bottom,
+            "right" => Self::Right,
+            "top_left" => Self::TopLeft,
+            "top_right" => Self::TopRight,
+            "bottom_right" => Self::BottomRight,
+            "bottom_left" => Self::BottomLeft,
+            "abs" => Self::Abs,
+            _ => bail!("Invalid resize handle {s}"),
+        })
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::North => "north",
+            Self::East => "east",
+            Self::South => "south",
+            Self::West => "west",
+        })
+    }
+}
+
+impl fmt::Display for StackSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prev => f.write_str("stack.prev"),
+            Self::Next => f.write_str("stack.next"),
+            Self::First => f.write_str("stack.first"),
+            Self::Last => f.write_str("stack.last"),
+            Self::Recent => f.write_str("stack.recent"),
+            Self::Index(index) => write!(f, "stack.{index}"),
+        }
+    }
+}
+
+impl fmt::Display for WindowSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prev => f.write_str("prev"),
+            Self::Next => f.write_str("next"),
+            Self::First => f.write_str("first"),
+            Self::Last => f.write_str("last"),
+            Self::Recent => f.write_str("recent"),
+            Self::Mouse => f.write_str("mouse"),
+            Self::Largest => f.write_str("largest"),
+            Self::Smallest => f.write_str("smallest"),
+            Self::Sibling => f.write_str("sibling"),
+            Self::FirstNephew => f.write_str("first_nephew"),
+            Self::SecondNephew => f.write_str("second_nephew"),
+            Self::Uncle => f.write_str("uncle"),
+            Self::FirstCousin => f.write_str("first_cousin"),
+            Self::SecondCousin => f.write_str("second_cousin"),
+            Self::Direction(direction) => direction.fmt(f),
+            Self::Stack(stack) => stack.fmt(f),
+            Self::Id(id) => id.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for SpaceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prev => f.write_str("prev"),
+            Self::Next => f.write_str("next"),
+            Self::First => f.write_str("first"),
+            Self::Last => f.write_str("last"),
+            Self::Recent => f.write_str("recent"),
+            Self::Mouse => f.write_str("mouse"),
+            Self::Index(index) => index.fmt(f),
+            Self::Label(label) => f.write_str(label),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Bsp => "bsp",
+            Self::Stack => "stack",
+            Self::Float => "float",
+        })
+    }
+}
+
+impl fmt::Display for ResizeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Top => "top",
+            Self::Left => "left",
+            Self::Bottom => "bottom",
+            Self::Right => "right",
+            Self::TopLeft => "top_left",
+            Self::TopRight => "top_right",
+            Self::BottomRight => "bottom_right",
+            Self::BottomLeft => "bottom_left",
+            Self::Abs => "abs",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_args() {
+        let resize = |handle, dx, dy| Command::window(WindowAction::Resize(handle, dx, dy));
+        assert_eq!(
+            resize(ResizeHandle::TopLeft, 20, -10).args(),
+            ["window", "--resize", "top_left:+20:-10"]
+        );
+        assert_eq!(
+            resize(ResizeHandle::Right, 0, 0).args(),
+            ["window", "--resize", "right:+0:+0"]
+        );
+        assert_eq!(
+            resize(ResizeHandle::Abs, 800, 600).args(),
+            ["window", "--resize", "abs:800:600"]
+        );
+
+        let command = Command::window_at(
+            WindowSelector::Id(42),
+            WindowAction::Space(SpaceSelector::Label("code".into())),
+        );
+        assert_eq!(command.args(), ["window", "42", "--space", "code"]);
+        let command = Command::window(WindowAction::Focus(WindowSelector::Stack(
+            StackSelector::Index(2),
+        )));
+        assert_eq!(command.to_string(), "window --focus stack.2");
+    }
+
+    #[test]
+    fn space_and_rule_args() {
+        let command = Command::space_at(
+            SpaceSelector::Index(3),
+            SpaceAction::Padding(Padding {
+                top: 1,
+                bottom: 2,
+                left: 3,
+                right: 4,
+            }),
+        );
+        assert_eq!(command.args(), ["space", "3", "--padding", "abs:1:2:3:4"]);
+        assert_eq!(
+            Command::space(SpaceAction::Layout(Layout::Bsp)).args(),
+            ["space", "--layout", "bsp"]
+        );
+
+        let command = Command::Rule(RuleAction::Add {
+            label: Some("scratchpad".into()),
+            filters: vec!["app=^Notes$".into()],
+            grid: None,
+            manage: Some(false),
+        });
+        assert_eq!(
+            command.args(),
+            [
+                "rule",
+                "--add",
+                "label=scratchpad",
+                "app=^Notes$",
+                "manage=off"
+            ]
+        );
+    }
+
+    #[test]
+    fn selectors_round_trip() {
+        for s in [
+            "prev",
+            "next",
+            "first",
+            "last",
+            "recent",
+            "mouse",
+            "largest",
+            "smallest",
+            "sibling",
+            "first_nephew",
+            "second_nephew",
+            "uncle",
+            "first_cousin",
+            "second_cousin",
+            "north",
+            "east",
+            "south",
+            "west",
+            "stack.prev",
+            "stack.next",
+            "stack.first",
+            "stack.last",
+            "stack.recent",
+            "stack.3",
+            "12345",
+        ] {
+            assert_eq!(s.parse::<WindowSelector>().unwrap().to_string(), s);
+        }
+        for s in [
+            "prev", "next", "first", "last", "recent", "mouse", "2", "code",
+        ] {
+            assert_eq!(s.parse::<SpaceSelector>().unwrap().to_string(), s);
+        }
+        for s in ["bsp", "stack", "float"] {
+            assert_eq!(s.parse::<Layout>().unwrap().to_string(), s);
+        }
+        for s in [
+            "top",
+            "left",
+            "bottom",
+            "right",
+            "top_left",
+            "top_right",
+            "bottom_right",
+            "bottom_left",
+            "abs",
+        ] {
+            assert_eq!(s.parse::<ResizeHandle>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_selectors() {
+        for s in ["", "my label"] {
+            assert!(s.parse::<SpaceSelector>().is_err(), "{s}");
+        }
+        // Labels resembling keywords are labels all the same.
+        for s in ["list", "text", "nxet"] {
+            assert_eq!(
+                s.parse::<SpaceSelector>().unwrap(),
+                SpaceSelector::Label(s.into())
+            );
+        }
+        for s in ["nxet", "stack.", "stack.top", "up", "-1"] {
+            assert!(s.parse::<WindowSelector>().is_err(), "{s}");
+        }
+        assert!("tiled".parse::<Layout>().is_err());
+        assert!("middle".parse::<ResizeHandle>().is_err());
+    }
+}
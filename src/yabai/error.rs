@@ -0,0 +1,61 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Errors talking to the yabai socket.
+#[derive(Debug)]
+pub enum Error {
+    /// Unable to connect to the socket, most likely because yabai isn't running.
+    NotRunning { path: String, source: io::Error },
+    /// yabai received the command and refused it.
+    Rejected { message: String, args: Vec<String> },
+    /// yabai responded with something that couldn't be understood.
+    Malformed { response: String, reason: String },
+    /// yabai didn't respond in time.
+    Timeout { after: Duration, args: Vec<String> },
+    /// Argument that can't be sent to yabai.
+    InvalidArgument(String),
+    /// Connection failed after being established.
+    Io(io::Error),
+}
+
+impl Error {
+    /// Whether `error` is yabai refusing a command, e.g. because no window was selected.
+    pub fn is_rejection(error: &anyhow::Error) -> bool {
+        matches!(error.downcast_ref(), Some(Error::Rejected { .. }))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotRunning { path, source } => {
+                write!(f, "Yabai is not running ({path}: {source})")
+            }
+            Error::Rejected { message, args } => write!(f, "Yabai: {message} {args:?}"),
+            Error::Malformed { response, reason } => {
+                write!(f, "Yabai: malformed response ({reason}): {response}")
+            }
+            Error::Timeout { after, args } => {
+                write!(f, "Yabai: no response after {after:?} {args:?}")
+            }
+            Error::InvalidArgument(arg) => write!(f, "Unexpected NUL byte in argument {arg:?}"),
+            Error::Io(e) => write!(f, "Yabai: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::NotRunning { source, .. } | Error::Io(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
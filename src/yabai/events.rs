@@ -28,6 +28,14 @@ pub enum MissionControlEvent {
 
 #[derive(Debug)]
 pub enum SpaceEvent {
+    /// Triggered when a space is created.
+    ///
+    /// Passes one argument: $YABAI_SPACE_ID
+    Created { space_id: u32 },
+    /// Triggered when a space is destroyed.
+    ///
+    /// Passes one argument: $YABAI_SPACE_ID
+    Destroyed { space_id: u32 },
     /// Triggered when the active space has changed.
     ///
     /// Passes two arguments: $YABAI_SPACE_ID, $YABAI_RECENT_SPACE_ID
@@ -125,6 +133,90 @@ pub enum ApplicationEvent {
     /// Passes one argument: $YABAI_PROCESS_ID
     Hidden,
 }
+
+/// Names of supported events, as used by yabai signals.
+pub const EVENT_NAMES: &[&str] = &[
+    "window_created",
+    "window_destroyed",
+    "window_focused",
+    "window_moved",
+    "window_resized",
+    "window_minimized",
+    "window_deminimized",
+    "space_created",
+    "space_destroyed",
+    "space_changed",
+    "display_added",
+    "display_removed",
+    "display_moved",
+    "display_resized",
+    "display_changed",
+    "mission_control_enter",
+    "mission_control_exit",
+    "application_visible",
+    "application_hidden",
+];
+
+impl Event {
+    /// Name of the event, as used by yabai signals.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::NotSupported => "not_supported",
+            Event::MissionControl(MissionControlEvent::Enter) => "mission_control_enter",
+            Event::MissionControl(MissionControlEvent::Exit) => "mission_control_exit",
+            Event::Window(event) => match event {
+                WindowEvent::Created { .. } => "window_created",
+                WindowEvent::Destroyed { .. } => "window_destroyed",
+                WindowEvent::Focused { .. } => "window_focused",
+                WindowEvent::Moved { .. } => "window_moved",
+                WindowEvent::Resized { .. } => "window_resized",
+                WindowEvent::Minimized { .. } => "window_minimized",
+                WindowEvent::Deminimized { .. } => "window_deminimized",
+            },
+            Event::Display(event) => match event {
+                DisplayEvent::Added => "display_added",
+                DisplayEvent::Removed => "display_removed",
+                DisplayEvent::Moved => "display_moved",
+                DisplayEvent::Resized => "display_resized",
+                DisplayEvent::Changed => "display_changed",
+            },
+            Event::Space(event) => match event {
+                SpaceEvent::Created { .. } => "space_created",
+                SpaceEvent::Destroyed { .. } => "space_destroyed",
+                SpaceEvent::Changed { .. } => "space_changed",
+            },
+            Event::Application(ApplicationEvent::Visible) => "application_visible",
+            Event::Application(ApplicationEvent::Hidden) => "application_hidden",
+        }
+    }
+
+    /// Ids carried by the event, e.g. `("window_id", 42)`.
+    pub fn ids(&self) -> Vec<(&'static str, u32)> {
+        match self {
+            Event::Window(
+                WindowEvent::Created { window_id }
+                | WindowEvent::Destroyed { window_id }
+                | WindowEvent::Focused { window_id }
+                | WindowEvent::Moved { window_id }
+                | WindowEvent::Resized { window_id }
+                | WindowEvent::Minimized { window_id }
+                | WindowEvent::Deminimized { window_id },
+            ) => vec![("window_id", *window_id)],
+            Event::Space(SpaceEvent::Created { space_id } | SpaceEvent::Destroyed { space_id }) => {
+                vec![("space_id", *space_id)]
+            }
+            Event::Space(SpaceEvent::Changed {
+                space_id,
+                recent_space_id,
+            }) => vec![
+                ("space_id", *space_id),
+                ("recent_space_id", *recent_space_id),
+            ],
+            _ => vec![],
+        }
+    }
+}
+
 impl TryFrom<Vec<&str>> for Event {
     type Error = Error;
     fn try_from(args: Vec<&str>) -> Result<Self, Error> {
@@ -159,6 +251,14 @@ impl TryFrom<Vec<&str>> for Event {
             Self::Window(WindowEvent::Deminimized {
                 window_id: args.get(1).unwrap().parse::<u32>()?,
             })
+        } else if SPACE_CREATED == val {
+            Self::Space(SpaceEvent::Created {
+                space_id: args.get(1).unwrap().parse::<u32>()?,
+            })
+        } else if SPACE_DESTROYED == val {
+            Self::Space(SpaceEvent::Destroyed {
+                space_id: args.get(1).unwrap().parse::<u32>()?,
+            })
         } else if SPACE_CHANGED == val {
             Self::Space(SpaceEvent::Changed {
                 space_id: args.get(1).unwrap().parse::<u32>()?,
@@ -188,7 +288,7 @@ impl TryFrom<Vec<&str>> for Event {
 
         match event {
             Self::NotSupported => {
-                let event = std::str::from_utf8(&val)?;
+                let event = std::str::from_utf8(val)?;
                 bail!("Event {event} is not supported.")
             }
             _ => Ok(event),
@@ -232,6 +332,8 @@ impl fmt::Display for Event {
                 DisplayEvent::Changed => write!(f, "Display Changed"),
             },
             Event::Space(event) => match event {
+                SpaceEvent::Created { space_id } => write!(f, "Space Created ({space_id})"),
+                SpaceEvent::Destroyed { space_id } => write!(f, "Space Destroyed ({space_id})"),
                 SpaceEvent::Changed {
                     space_id,
                     recent_space_id,
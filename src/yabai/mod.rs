@@ -1,7 +1,13 @@
+mod command;
+mod error;
 mod events;
 mod models;
+mod snapshot;
 mod socket;
 
+pub use command::*;
+pub use error::Error;
 pub use events::*;
 pub use models::*;
-pub use socket::Socket;
+pub use snapshot::Snapshot;
+pub use socket::{set_timeout, Socket};
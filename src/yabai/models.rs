@@ -1,36 +1,47 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+// NOTE: Fields missing from older/newer yabai versions fallback to their default values
+// rather than failing the whole query.
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
 pub struct Space {
     pub id: u32,
-    uuid: String,
+    pub uuid: String,
     pub index: u32,
     pub label: String,
     pub r#type: String,
     pub display: u32,
     pub windows: Vec<u32>,
-    #[serde(rename(deserialize = "first-window"))]
+    #[serde(rename = "first-window")]
     pub first_window: u32,
-    #[serde(rename(deserialize = "last-window"))]
+    #[serde(rename = "last-window")]
     pub last_window: u32,
-    #[serde(rename(deserialize = "has-focus"))]
+    #[serde(rename = "has-focus")]
     pub has_focus: bool,
-    #[serde(rename(deserialize = "is-visible"))]
+    #[serde(rename = "is-visible")]
     pub is_visible: bool,
-    #[serde(rename(deserialize = "is-native-fullscreen"))]
+    #[serde(rename = "is-native-fullscreen")]
     pub is_native_fullscreen: bool,
+    #[serde(rename = "is-sticky")]
+    pub is_sticky: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
 pub struct Display {
-    id: u32,
-    uuid: String,
-    index: u32,
-    frame: Frame,
-    spaces: Vec<u32>,
+    pub id: u32,
+    pub uuid: String,
+    pub index: u32,
+    pub label: String,
+    pub frame: Frame,
+    pub spaces: Vec<u32>,
+    #[serde(rename = "has-focus")]
+    pub has_focus: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(default)]
 pub struct Frame {
     pub x: f32,
     pub y: f32,
@@ -38,51 +49,251 @@ pub struct Frame {
     pub h: f32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
 pub struct Window {
     pub id: u32,
     pub pid: u32,
     pub app: String,
     pub title: String,
+    pub scratchpad: String,
     pub frame: Frame,
     pub role: String,
     pub subrole: String,
+    #[serde(rename = "root-window")]
+    pub root_window: bool,
     pub display: u32,
     pub space: u32,
-    pub level: u32,
+    pub level: i32,
+    #[serde(rename = "sub-level")]
+    pub sub_level: i32,
+    pub layer: String,
+    #[serde(rename = "sub-layer")]
+    pub sub_layer: String,
     pub opacity: f32,
-    #[serde(rename(deserialize = "split-type"))]
+    #[serde(rename = "split-type")]
     pub split_type: String,
-    #[serde(rename(deserialize = "stack-index"))]
+    #[serde(rename = "split-child")]
+    pub split_child: String,
+    #[serde(rename = "stack-index")]
     pub stack_index: u32,
-    #[serde(rename(deserialize = "can-move"))]
+    #[serde(rename = "can-move")]
     pub is_moveable: bool,
-    #[serde(rename(deserialize = "can-resize"))]
+    #[serde(rename = "can-resize")]
     pub is_resizeable: bool,
-    #[serde(rename(deserialize = "has-focus"))]
+    #[serde(rename = "has-focus")]
     pub has_focus: bool,
-    #[serde(rename(deserialize = "has-shadow"))]
+    #[serde(rename = "has-shadow")]
     pub has_shadow: bool,
-    #[serde(rename(deserialize = "has-border"))]
+    #[serde(rename = "has-border")]
     pub has_border: bool,
-    #[serde(rename(deserialize = "has-parent-zoom"))]
+    #[serde(rename = "has-parent-zoom")]
     pub has_parent_zoom: bool,
-    #[serde(rename(deserialize = "has-fullscreen-zoom"))]
+    #[serde(rename = "has-fullscreen-zoom")]
     pub has_fullscreen_zoom: bool,
-    #[serde(rename(deserialize = "is-native-fullscreen"))]
+    #[serde(rename = "has-ax-reference")]
+    pub has_ax_reference: bool,
+    #[serde(rename = "is-native-fullscreen")]
     pub is_native_fullscreen: bool,
-    #[serde(rename(deserialize = "is-visible"))]
+    #[serde(rename = "is-visible")]
     pub is_visible: bool,
-    #[serde(rename(deserialize = "is-minimized"))]
+    #[serde(rename = "is-minimized")]
     pub is_minimized: bool,
-    #[serde(rename(deserialize = "is-hidden"))]
+    #[serde(rename = "is-hidden")]
     pub is_hidden: bool,
-    #[serde(rename(deserialize = "is-floating"))]
+    #[serde(rename = "is-floating")]
     pub is_floating: bool,
-    #[serde(rename(deserialize = "is-sticky"))]
+    #[serde(rename = "is-sticky")]
     pub is_sticky: bool,
-    #[serde(rename(deserialize = "is-topmost"))]
+    #[serde(rename = "is-topmost")]
     pub is_topmost: bool,
-    #[serde(rename(deserialize = "is-grabbed"))]
+    #[serde(rename = "is-grabbed")]
     pub is_grabbed: bool,
 }
+
+impl Window {
+    /// Whether the window is shown to the user, excluding minimized, hidden and helper windows.
+    pub fn is_shown(&self) -> bool {
+        self.subrole != "AXUnknown.Hammerspoon" && !self.is_minimized && !self.is_hidden
+    }
+
+    /// Zoom of the window, yabai zooms a window to its parent or to fullscreen, not both.
+    pub fn zoom(&self) -> Zoom {
+        match (self.has_fullscreen_zoom, self.has_parent_zoom) {
+            (true, _) => Zoom::Fullscreen,
+            (false, true) => Zoom::Parent,
+            (false, false) => Zoom::None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Zoom {
+    #[default]
+    None,
+    Parent,
+    Fullscreen,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Rule {
+    pub index: u32,
+    pub label: String,
+    pub app: String,
+    pub title: String,
+    pub role: String,
+    pub subrole: String,
+    pub display: u32,
+    pub space: u32,
+    pub follow_space: bool,
+    pub opacity: f32,
+    pub manage: Option<bool>,
+    pub sticky: Option<bool>,
+    pub mouse_follows_focus: Option<bool>,
+    pub layer: String,
+    #[serde(rename = "sub-layer")]
+    pub sub_layer: String,
+    #[serde(rename = "native-fullscreen")]
+    pub native_fullscreen: Option<bool>,
+    pub grid: String,
+    pub scratchpad: String,
+    #[serde(rename = "one-shot")]
+    pub one_shot: bool,
+    pub flags: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Signal {
+    pub index: u32,
+    pub label: String,
+    pub app: String,
+    pub title: String,
+    pub active: Option<bool>,
+    pub event: String,
+    pub action: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    const VERSIONS: &[&str] = &["4.0.0", "5.0.0", "6.0.0"];
+
+    fn fixture(version: &str, name: &str) -> String {
+        let path = format!(
+            "{}/tests/fixtures/yabai-{version}/{name}.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"))
+    }
+
+    /// Fixture parsed as `T`, along with its raw objects.
+    fn parse<T: serde::de::DeserializeOwned>(version: &str, name: &str) -> (Vec<T>, Vec<Value>) {
+        let raw = fixture(version, name);
+        let parsed = serde_json::from_str(&raw).unwrap_or_else(|e| panic!("yabai {version}: {e}"));
+        (parsed, serde_json::from_str(&raw).unwrap())
+    }
+
+    /// Fields yabai reports that `T` would silently drop.
+    fn unmodeled<T: Serialize>(parsed: &[T], raw: &[Value]) -> Vec<String> {
+        let modeled = serde_json::to_value(parsed).unwrap();
+        let modeled = modeled.as_array().unwrap();
+        raw.iter()
+            .zip(modeled)
+            .flat_map(|(raw, modeled)| {
+                let modeled = modeled.as_object().unwrap();
+                raw.as_object()
+                    .unwrap()
+                    .keys()
+                    .filter(|key| !modeled.contains_key(*key))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deserialize_windows() {
+        for version in VERSIONS {
+            let (windows, raw) = parse::<Window>(version, "windows");
+            assert!(!windows.is_empty(), "yabai {version}");
+            assert!(
+                windows.iter().all(|w| w.id > 0 && w.pid > 0),
+                "yabai {version}"
+            );
+            assert!(windows.iter().filter(|w| w.has_focus).count() <= 1);
+            assert_eq!(
+                unmodeled(&windows, &raw),
+                [] as [String; 0],
+                "yabai {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_spaces() {
+        for version in VERSIONS {
+            let (spaces, raw) = parse::<Space>(version, "spaces");
+            let indices = spaces.iter().map(|s| s.index).collect::<Vec<_>>();
+            let expected = (1..=spaces.len() as u32).collect::<Vec<_>>();
+            assert_eq!(indices, expected, "yabai {version}");
+            assert_eq!(spaces.iter().filter(|s| s.has_focus).count(), 1);
+            assert_eq!(
+                unmodeled(&spaces, &raw),
+                [] as [String; 0],
+                "yabai {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_displays() {
+        for version in VERSIONS {
+            let (displays, raw) = parse::<Display>(version, "displays");
+            let (spaces, _) = parse::<Space>(version, "spaces");
+            let mut indices = displays
+                .iter()
+                .flat_map(|d| d.spaces.clone())
+                .collect::<Vec<_>>();
+            indices.sort();
+            assert_eq!(indices.len(), spaces.len(), "yabai {version}");
+            assert!(displays.iter().all(|d| d.frame.w > 0.0), "yabai {version}");
+            assert_eq!(
+                unmodeled(&displays, &raw),
+                [] as [String; 0],
+                "yabai {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_rules_and_signals() {
+        for version in VERSIONS {
+            let (rules, raw) = parse::<Rule>(version, "rules");
+            assert_eq!(
+                unmodeled(&rules, &raw),
+                [] as [String; 0],
+                "yabai {version}"
+            );
+            let (signals, raw) = parse::<Signal>(version, "signals");
+            assert_eq!(
+                unmodeled(&signals, &raw),
+                [] as [String; 0],
+                "yabai {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_with_missing_fields() {
+        let window: Window = serde_json::from_str(r#"{"id": 1, "app": "Finder"}"#).unwrap();
+        assert_eq!(window.id, 1);
+        assert!(!window.is_floating);
+
+        let space: Space = serde_json::from_str(r#"{"id": 1, "unknown": [1]}"#).unwrap();
+        assert!(space.windows.is_empty());
+    }
+}
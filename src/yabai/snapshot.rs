@@ -0,0 +1,168 @@
+use super::models::{Display, Space, Window};
+
+/// Consistent view of yabai displays, spaces and windows at a point in time.
+#[derive(Debug, Default, Clone)]
+pub struct Snapshot {
+    displays: Vec<Display>,
+    spaces: Vec<Space>,
+    windows: Vec<Window>,
+}
+
+impl Snapshot {
+    pub fn new(displays: Vec<Display>, spaces: Vec<Space>, windows: Vec<Window>) -> Self {
+        Self {
+            displays,
+            spaces,
+            windows,
+        }
+    }
+
+    pub fn spaces(&self) -> &[Space] {
+        &self.spaces
+    }
+
+    /// All windows, including minimized and hidden ones.
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    pub fn space(&self, id: u32) -> Option<&Space> {
+        self.spaces.iter().find(|s| s.id == id)
+    }
+
+    pub fn window(&self, id: u32) -> Option<&Window> {
+        self.windows.iter().find(|w| w.id == id)
+    }
+
+    pub fn focused_space(&self) -> Option<&Space> {
+        self.spaces.iter().find(|s| s.has_focus)
+    }
+
+    pub fn focused_window(&self) -> Option<&Window> {
+        self.windows.iter().find(|w| w.has_focus)
+    }
+
+    /// Windows in the given space, including minimized and hidden ones.
+    pub fn windows_in<'a>(&'a self, space: &'a Space) -> impl Iterator<Item = &'a Window> {
+        self.windows.iter().filter(|w| w.space == space.index)
+    }
+
+    pub fn space_of(&self, window: &Window) -> Option<&Space> {
+        self.spaces.iter().find(|s| s.index == window.space)
+    }
+
+    /// Describe the first disagreement between spaces and windows, if any.
+    ///
+    /// Queries made while yabai is processing a change (e.g. a window moving to another
+    /// space) may reflect different states.
+    pub fn inconsistency(&self) -> Option<String> {
+        for space in &self.spaces {
+            if let Some(window) = space
+                .windows
+                .iter()
+                .filter_map(|id| self.window(*id))
+                .find(|w| w.space != space.index)
+            {
+                return Some(format!(
+                    "space {} lists window {} which is in space {}",
+                    space.index, window.id, window.space
+                ));
+            }
+        }
+
+        // Sticky and minimized windows may be left out of the window list of their space.
+        let listed = self
+            .windows
+            .iter()
+            .filter(|w| w.space != 0 && !w.is_sticky && !w.is_minimized);
+        for window in listed {
+            match self.space_of(window) {
+                None => {
+                    return Some(format!(
+                        "window {} is in unknown space {}",
+                        window.id, window.space
+                    ))
+                }
+                Some(space) if !space.windows.contains(&window.id) => {
+                    return Some(format!(
+                        "window {} is in space {} which doesn't list it",
+                        window.id, space.index
+                    ))
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(space) = self
+            .spaces
+            .iter()
+            .find(|s| !self.displays.iter().any(|d| d.index == s.display))
+        {
+            return Some(format!(
+                "space {} is on unknown display {}",
+                space.index, space.display
+            ));
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture<T: serde::de::DeserializeOwned>(name: &str) -> T {
+        let path = format!("{}/tests/fixtures/{name}.json", env!("CARGO_MANIFEST_DIR"));
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot::new(
+            fixture("yabai-6.0.0/displays"),
+            fixture("yabai-6.0.0/spaces"),
+            fixture("yabai-6.0.0/windows"),
+        )
+    }
+
+    #[test]
+    fn lookup() {
+        let snapshot = snapshot();
+        assert!(snapshot.inconsistency().is_none());
+
+        let window = snapshot.focused_window().unwrap();
+        let space = snapshot.space_of(window).unwrap();
+        assert!(snapshot.windows_in(space).any(|w| w.id == window.id));
+    }
+
+    #[test]
+    fn detect_moving_window() {
+        let mut snapshot = snapshot();
+        let id = snapshot.spaces[0].windows[0];
+        snapshot.spaces[1].windows.push(id);
+        snapshot.spaces[0].windows.retain(|w| *w != id);
+
+        assert!(snapshot.inconsistency().is_some());
+    }
+
+    #[test]
+    fn detect_window_missing_from_its_space() {
+        let mut snapshot = snapshot();
+        // The window left its space, which yabai already updated, but not the window yet.
+        let id = snapshot.spaces[0].windows.remove(0);
+
+        let reason = snapshot.inconsistency().unwrap();
+        assert!(reason.contains(&format!("window {id}")), "{reason}");
+    }
+
+    #[test]
+    fn ignore_unlisted_sticky_and_minimized_windows() {
+        let mut snapshot = snapshot();
+        let unlisted: Vec<Window> = fixture("snapshot/unlisted-windows");
+        assert!(unlisted.iter().any(|w| w.is_sticky));
+        assert!(unlisted.iter().any(|w| w.is_minimized));
+        snapshot.windows.extend(unlisted);
+
+        assert_eq!(snapshot.inconsistency(), None);
+    }
+}
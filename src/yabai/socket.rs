@@ -1,44 +1,96 @@
-use super::models::{Space, Window};
-use anyhow::{bail, Context, Result};
+use super::command::Command;
+use super::error::Error;
+use super::models::{Display, Rule, Signal, Space, Window};
+use super::snapshot::Snapshot;
+use crate::metrics;
+use crate::trace::{self, Entry};
+use anyhow::{Context, Result};
 use serde::de::DeserializeOwned;
 use std::env;
 use std::fmt::Debug;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::UnixStream;
+use tokio::time;
 
 const QUERY_GET_SPACE_WINDOWS: &[&str; 3] = &["query", "--windows", "--space"];
 const QUERY_GET_FOCUSED_WINDOW: &[&str; 3] = &["query", "--windows", "--window"];
 const QUERY_GET_FOCUSED_SPACE: &[&str; 3] = &["query", "--spaces", "--space"];
 const QUERY_GET_ALL_WINDOWS: &[&str; 2] = &["query", "--windows"];
 const QUERY_GET_ALL_SPACES: &[&str; 2] = &["query", "--spaces"];
+const QUERY_GET_ALL_DISPLAYS: &[&str; 2] = &["query", "--displays"];
+const QUERY_GET_ALL_RULES: &[&str; 2] = &["rule", "--list"];
+const QUERY_GET_ALL_SIGNALS: &[&str; 2] = &["signal", "--list"];
 
+/// Time given to yabai to respond to a request, unless configured otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time given to yabai to respond to a request, as configured with `--yabai-timeout`.
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+/// Number of attempts for queries answered with an empty response.
+const QUERY_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled for each subsequent one.
+const QUERY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Number of attempts at getting a consistent snapshot.
+const SNAPSHOT_ATTEMPTS: u32 = 3;
+
+/// Leading byte of a response signaling that the command failed.
+const FAILURE_MESSAGE: u8 = 0x07;
+
+#[derive(Debug, Clone)]
 pub struct Socket {
     socket_path: String,
+    timeout: Duration,
 }
 
 impl Socket {
-    pub fn new() -> Result<Self> {
-        let user = env::var("USER")?;
-        let socket_path = format!("/tmp/yabai_{user}.socket");
-        Ok(Self { socket_path })
+    pub fn new() -> anyhow::Result<Self> {
+        let socket_path = match trace::replay_socket() {
+            Some(path) => path,
+            None => {
+                let user = env::var("USER").context("USER is not set")?;
+                format!("/tmp/yabai_{user}.socket")
+            }
+        };
+        Ok(Self {
+            socket_path,
+            timeout: TIMEOUT.get().copied().unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+
+    /// Socket giving yabai at least `timeout` to respond to each request, for requests
+    /// known to be slow.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: self.timeout.max(timeout),
+            ..self.clone()
+        }
     }
 
     /// Send given arguments to yabai and return a stream for further processing
-    async fn send<A: AsRef<[u8]>>(self: &Self, args: &[A]) -> Result<UnixStream> {
-        let mut stream = UnixStream::connect(&self.socket_path).await?;
+    async fn send<A: AsRef<[u8]>>(&self, args: &[A]) -> Result<UnixStream, Error> {
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .await
+            .map_err(|source| Error::NotRunning {
+                path: self.socket_path.clone(),
+                source,
+            })?;
 
-        stream.writable().await?;
         let mut command = Vec::from([0x0, 0x0, 0x0, 0x0]);
         for arg in args.iter().map(AsRef::as_ref) {
             if arg.contains(&0x0) {
-                bail!("Internal: Unexpected NUL byte in arg: {arg:?}");
+                return Err(Error::InvalidArgument(
+                    String::from_utf8_lossy(arg).into_owned(),
+                ));
             }
             command.extend_from_slice(arg);
             command.push(0x0)
         }
 
         command.push(0x0);
-        command[0] = (command.len() - 4) as u8;
+        let len = (command.len() - 4) as u32;
+        command[..4].copy_from_slice(&len.to_le_bytes());
 
         stream.write_all(&command).await?;
         stream.flush().await?;
@@ -47,77 +99,145 @@ impl Socket {
     }
 
     /// Send request to yabai socket and return string.
-    pub async fn request<A: AsRef<[u8]> + Debug>(self: &Self, args: &[A]) -> Result<String> {
-        let mut stream = self.send(args).await?;
-        let mut buf = Vec::new();
-
-        // Wait till the stream become readable.
-        stream.readable().await?;
-        // Read till EOF
-        stream.read_to_end(&mut buf).await?;
+    pub async fn request<A: AsRef<[u8]> + Debug>(&self, args: &[A]) -> Result<String> {
+        let started = Instant::now();
+        let result = self.exchange(args).await;
+        metrics::observe_yabai(&command_name(args), started.elapsed(), result.is_ok());
+        result
+    }
+
+    async fn exchange<A: AsRef<[u8]> + Debug>(&self, args: &[A]) -> Result<String> {
+        let exchange = async {
+            let mut stream = self.send(args).await?;
+            let mut buf = Vec::new();
+            // Read till EOF
+            stream.read_to_end(&mut buf).await?;
+            Ok::<_, Error>(buf)
+        };
+
+        let result = match time::timeout(self.timeout, exchange).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::Timeout {
+                after: self.timeout,
+                args: display_args(args),
+            }),
+        };
+        if trace::enabled() {
+            trace::record(Entry::Yabai {
+                args: display_args(args),
+                response: result
+                    .as_ref()
+                    .ok()
+                    .map(|buf| String::from_utf8_lossy(buf).into_owned()),
+            });
+        }
+        let buf = result?;
+
         // Check if yabai errored
-        if buf.get(0) == Some(&7) {
-            anyhow::bail!(
-                "Yabai: {} {:?}",
-                String::from_utf8_lossy(&buf[1..]).trim(),
-                args
-            );
+        if buf.first() == Some(&FAILURE_MESSAGE) {
+            return Err(Error::Rejected {
+                message: String::from_utf8_lossy(&buf[1..]).trim().to_string(),
+                args: display_args(args),
+            }
+            .into());
         }
 
-        String::from_utf8(buf).map_err(anyhow::Error::new)
+        String::from_utf8(buf).map_err(|e| {
+            Error::Malformed {
+                response: String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                reason: e.to_string(),
+            }
+            .into()
+        })
     }
 
     /// Send request to yabai socket and ignore response unless it is an error response.
-    pub async fn execute<A: AsRef<[u8]> + Debug>(self: &Self, args: &[A]) -> Result<()> {
-        let mut buf = [0; 1];
-        let mut stream = self.send(args).await?;
-        // Wait till the stream become readable
-        stream.readable().await?;
-        // Ignore overflow error
-        stream.read_exact(&mut buf).await.ok();
-        // Check for error code
-        if buf.get(0) != Some(&7) {
-            Ok(())
-        } else {
-            bail!("Yabai: fail to execute {:?}", args)
-        }
+    pub async fn execute<A: AsRef<[u8]> + Debug>(&self, args: &[A]) -> Result<()> {
+        self.request(args).await.map(|_| ())
     }
 
-    pub async fn query<T, A>(self: &Self, args: &[A]) -> Result<T>
+    /// Run typed command, ignoring response unless it is an error response.
+    pub async fn run(&self, command: &Command) -> Result<()> {
+        self.execute(&command.args()).await
+    }
+
+    /// Request JSON from yabai, retrying with backoff when the response is empty.
+    pub async fn query<T, A>(&self, args: &[A]) -> Result<T>
     where
         T: DeserializeOwned,
         A: AsRef<[u8]> + Debug,
     {
-        loop {
+        let mut backoff = QUERY_BACKOFF;
+        for attempt in 1..=QUERY_ATTEMPTS {
             // NOTE: According to @slam, sometime queries return empty string.
             let raw = self.request(args).await?;
             if raw.is_empty() {
-                eprintln!("{:?} returned an empty string, retrying", args);
+                tracing::debug!("{args:?} returned an empty string ({attempt}/{QUERY_ATTEMPTS})");
+                if attempt < QUERY_ATTEMPTS {
+                    metrics::retried_query(&command_name(args));
+                    time::sleep(backoff).await;
+                    backoff *= 2;
+                }
                 continue;
             }
-            return serde_json::from_str(&raw)
-                .with_context(|| format!("Failed to desrialize JSON: {raw}"));
+            return serde_json::from_str(&raw).map_err(|e| {
+                Error::Malformed {
+                    reason: e.to_string(),
+                    response: raw,
+                }
+                .into()
+            });
+        }
+
+        Err(Error::Malformed {
+            response: String::default(),
+            reason: format!("empty response after {QUERY_ATTEMPTS} attempts"),
         }
+        .into())
     }
 
-    pub async fn focused_space(self: &Self) -> Result<Space> {
+    pub async fn focused_space(&self) -> Result<Space> {
         self.query::<Space, _>(QUERY_GET_FOCUSED_SPACE).await
     }
 
-    pub async fn focused_window(self: &Self) -> Result<Window> {
+    pub async fn focused_window(&self) -> Result<Window> {
         self.query::<Window, _>(QUERY_GET_FOCUSED_WINDOW).await
     }
+    pub async fn window(&self, id: &u32) -> Result<Window> {
+        self.query::<Window, _>(&["query", "--windows", "--window", &id.to_string()])
+            .await
+    }
+
+    /// Window under the mouse cursor, if any.
+    pub async fn mouse_window(&self) -> Option<Window> {
+        self.query::<Window, _>(&["query", "--windows", "--window", "mouse"])
+            .await
+            .ok()
+    }
+
     pub async fn last_window(&self) -> Result<Window> {
         self.query::<Window, _>(&["query", "--windows", "--window", "last"])
             .await
     }
 
-    pub async fn spaces(self: &Self, _display: &str) -> Result<Vec<Space>> {
+    pub async fn spaces(&self, _display: &str) -> Result<Vec<Space>> {
         // reserved for current display/all displays
         self.query::<Vec<Space>, _>(QUERY_GET_ALL_SPACES).await
     }
 
-    pub async fn windows(self: &Self, space: &str) -> Result<Vec<Window>> {
+    pub async fn displays(&self) -> Result<Vec<Display>> {
+        self.query::<Vec<Display>, _>(QUERY_GET_ALL_DISPLAYS).await
+    }
+
+    pub async fn rules(&self) -> Result<Vec<Rule>> {
+        self.query::<Vec<Rule>, _>(QUERY_GET_ALL_RULES).await
+    }
+
+    pub async fn signals(&self) -> Result<Vec<Signal>> {
+        self.query::<Vec<Signal>, _>(QUERY_GET_ALL_SIGNALS).await
+    }
+
+    pub async fn windows(&self, space: &str) -> Result<Vec<Window>> {
         let windows = if space == "current" {
             self.query::<Vec<Window>, _>(QUERY_GET_SPACE_WINDOWS)
                 .await?
@@ -127,22 +247,185 @@ impl Socket {
             self.query::<Vec<Window>, _>(&["query", "--windows", "--space", space])
                 .await?
         };
-        Ok(windows
-            .into_iter()
-            .filter(|w| w.subrole != "AXUnknown.Hammerspoon" && !w.is_minimized && !w.is_hidden)
-            .collect())
+        Ok(windows.into_iter().filter(Window::is_shown).collect())
     }
 
-    pub async fn window_by_id(self: &Self, space: &str, id: &u32) -> Result<Option<Window>> {
-        let windows = if space == "current" {
-            self.query::<Vec<Window>, _>(QUERY_GET_SPACE_WINDOWS)
-                .await?
-        } else if space == "all" {
-            self.query::<Vec<Window>, _>(QUERY_GET_ALL_WINDOWS).await?
-        } else {
-            self.query::<Vec<Window>, _>(&["query", "--windows", "--space", space])
-                .await?
+    /// Fetch displays, spaces and windows concurrently, re-querying when they disagree.
+    pub async fn snapshot(&self) -> Result<Snapshot> {
+        let mut attempt = 1;
+        loop {
+            let (displays, spaces, windows) = tokio::try_join!(
+                self.displays(),
+                self.query::<Vec<Space>, _>(QUERY_GET_ALL_SPACES),
+                self.query::<Vec<Window>, _>(QUERY_GET_ALL_WINDOWS),
+            )?;
+            let snapshot = Snapshot::new(displays, spaces, windows);
+            match snapshot.inconsistency() {
+                None => return Ok(snapshot),
+                Some(reason) if attempt == SNAPSHOT_ATTEMPTS => {
+                    tracing::warn!("Using inconsistent snapshot: {reason}");
+                    return Ok(snapshot);
+                }
+                Some(reason) => {
+                    tracing::debug!(
+                        "Inconsistent snapshot ({attempt}/{SNAPSHOT_ATTEMPTS}): {reason}"
+                    );
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Give yabai `timeout` to respond to each request of the sockets created afterwards.
+///
+/// Only the first call has an effect.
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT.set(timeout).ok();
+}
+
+/// Name of the command sent to yabai, e.g. `query --windows` or `window --focus`, used to
+/// group metrics.
+///
+/// Selectors (window ids, space indices...) are left out to keep the number of names bounded.
+fn command_name<A: AsRef<[u8]>>(args: &[A]) -> String {
+    let args = display_args(args);
+    let domain = args.first().cloned().unwrap_or_default();
+    match args.iter().skip(1).find(|arg| arg.starts_with("--")) {
+        Some(action) => format!("{domain} {action}"),
+        None => domain,
+    }
+}
+
+fn display_args<A: AsRef<[u8]>>(args: &[A]) -> Vec<String> {
+    args.iter()
+        .map(|arg| String::from_utf8_lossy(arg.as_ref()).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    /// Fake yabai answering each connection with the next response, returning received
+    /// messages once all responses are sent.
+    fn fake_yabai(
+        name: &str,
+        responses: Vec<&'static [u8]>,
+    ) -> (Socket, tokio::task::JoinHandle<Vec<Vec<u8>>>) {
+        let socket_path = env::temp_dir()
+            .join(format!("yctrl-test-{name}-{}.socket", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::remove_file(&socket_path).ok();
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let server = tokio::spawn(async move {
+            let mut received = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut len = [0; 4];
+                stream.read_exact(&mut len).await.unwrap();
+                let mut message = vec![0; u32::from_le_bytes(len) as usize];
+                stream.read_exact(&mut message).await.unwrap();
+                received.push(message);
+                stream.write_all(response).await.unwrap();
+            }
+            received
+        });
+        let socket = Socket {
+            socket_path,
+            timeout: DEFAULT_TIMEOUT,
+        };
+        (socket, server)
+    }
+
+    #[tokio::test]
+    async fn send_long_command() {
+        let (yabai, server) = fake_yabai("long", vec![b""]);
+        let title = format!("title=^{}$", "x".repeat(300));
+        yabai.execute(&["rule", "--add", &title]).await.unwrap();
+
+        let received = server.await.unwrap();
+        assert_eq!(
+            received[0],
+            format!("rule\0--add\0{title}\0\0").into_bytes()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_command() {
+        let (yabai, _) = fake_yabai("rejected", vec![b"\x07could not locate window\n"]);
+        let error = yabai
+            .execute(&["window", "--focus", "west"])
+            .await
+            .unwrap_err();
+        match error.downcast_ref() {
+            Some(Error::Rejected { message, .. }) => assert_eq!(message, "could not locate window"),
+            e => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_empty_query() {
+        let (yabai, _) = fake_yabai("retry", vec![b"", b"", br#"{"id": 1}"#]);
+        let space: Space = yabai.query(QUERY_GET_FOCUSED_SPACE).await.unwrap();
+        assert_eq!(space.id, 1);
+    }
+
+    #[tokio::test]
+    async fn give_up_on_empty_query() {
+        let (yabai, _) = fake_yabai("empty", vec![b""; QUERY_ATTEMPTS as usize]);
+        let error = yabai.focused_space().await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(Error::Malformed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn not_running() {
+        let yabai = Socket {
+            socket_path: "/nonexistent/yabai.socket".into(),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        let error = yabai.focused_space().await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(Error::NotRunning { .. })
+        ));
+    }
+
+    #[test]
+    fn command_names_leave_selectors_out() {
+        assert_eq!(
+            command_name(&["query", "--windows", "--window", "3"]),
+            "query --windows"
+        );
+        assert_eq!(
+            command_name(&["window", "1234", "--focus", "east"]),
+            "window --focus"
+        );
+        assert_eq!(
+            command_name(&["space", "3", "--layout", "bsp"]),
+            "space --layout"
+        );
+        assert_eq!(command_name(&["config", "layout", "bsp"]), "config");
+    }
+
+    #[test]
+    fn slow_requests_never_get_less_time() {
+        let socket = Socket {
+            socket_path: String::default(),
+            timeout: Duration::from_secs(10),
         };
-        Ok(windows.into_iter().find(|w| &w.id == id))
+        assert_eq!(
+            socket.with_timeout(Duration::from_secs(5)).timeout,
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            socket.with_timeout(Duration::from_secs(20)).timeout,
+            Duration::from_secs(20)
+        );
     }
 }
@@ -0,0 +1,15 @@
+#!/bin/sh
+# Capture the output of the running yabai as compatibility fixtures, in yabai-<version>/.
+set -eu
+
+version=$(yabai --version | sed 's/^yabai-v//')
+dir="$(dirname "$0")/yabai-$version"
+mkdir -p "$dir"
+
+yabai -m query --displays >"$dir/displays.json"
+yabai -m query --spaces >"$dir/spaces.json"
+yabai -m query --windows >"$dir/windows.json"
+yabai -m rule --list >"$dir/rules.json"
+yabai -m signal --list >"$dir/signals.json"
+
+echo "Captured yabai $version to $dir, add it to VERSIONS in src/yabai/models.rs"